@@ -8,7 +8,7 @@ mod tests {
     #[bench]
     fn push(b: &mut Bencher) {
         const LEN: usize = 1024;
-        let buf: [u8; LEN] = unsafe { [std::mem::MaybeUninit::uninit().assume_init(); LEN] };
+        let buf = [0u8; LEN];
         let mut vf = VecFile::new();
         vf.reserve(LEN as u64).unwrap();
 
@@ -23,7 +23,7 @@ mod tests {
     #[bench]
     fn pop(b: &mut Bencher) {
         const LEN: usize = 1024;
-        let buf: [u8; LEN] = unsafe { [std::mem::MaybeUninit::uninit().assume_init(); LEN] };
+        let buf = [0u8; LEN];
         let mut vf = VecFile::new();
         for val in buf.iter() {
             vf.push(val);
@@ -42,7 +42,7 @@ mod tests {
     #[bench]
     fn read(b: &mut Bencher) {
         const LEN: usize = 1024;
-        let buf: [u8; LEN] = unsafe { [std::mem::MaybeUninit::uninit().assume_init(); LEN] };
+        let buf = [0u8; LEN];
         let mut vf = VecFile::new();
         for val in buf.iter() {
             vf.push(val);
@@ -58,16 +58,96 @@ mod tests {
     #[bench]
     fn set(b: &mut Bencher) {
         const LEN: usize = 1024;
-        let buf: [u8; LEN] = unsafe { [std::mem::MaybeUninit::uninit().assume_init(); LEN] };
+        let buf = [0u8; LEN];
         let mut vf = VecFile::new();
         vf.resize(LEN as u64, &0u8).unwrap();
 
         b.iter(|| {
             for (i, val) in buf.iter().enumerate() {
-                vf.set(i as u64, &val);
+                vf.set(i as u64, val);
             }
         });
     }
 
+    #[bench]
+    fn read_mmap(b: &mut Bencher) {
+        const LEN: usize = 1024;
+        let buf = [0u8; LEN];
+        let mut vf: VecFile<u8, MmapStorage> = VecFileBuilder::new().build().unwrap();
+        for val in buf.iter() {
+            vf.push(val);
+        }
+
+        b.iter(|| {
+            for i in 0u64..((LEN - 1) as u64) {
+                vf.get(i);
+            }
+        });
+    }
+
+    #[bench]
+    fn set_mmap(b: &mut Bencher) {
+        const LEN: usize = 1024;
+        let buf = [0u8; LEN];
+        let mut vf: VecFile<u8, MmapStorage> = VecFileBuilder::new().build().unwrap();
+        vf.resize(LEN as u64, &0u8).unwrap();
+
+        b.iter(|| {
+            for (i, val) in buf.iter().enumerate() {
+                vf.set(i as u64, val);
+            }
+        });
+    }
+
+    #[bench]
+    fn insert_remove_front(b: &mut Bencher) {
+        const LEN: usize = 1 << 16;
+        let mut vf = VecFile::new();
+        vf.resize(LEN as u64, &1u32).unwrap();
+
+        b.iter(|| {
+            // Every element is shifted both ways
+            vf.insert(0, &0);
+            vf.remove(0);
+        });
+    }
+
+    #[bench]
+    fn insert_slice_remove_range(b: &mut Bencher) {
+        const LEN: usize = 1 << 16;
+        let slice = [0u32; 1024];
+        let mut vf = VecFile::new();
+        vf.resize(LEN as u64, &1u32).unwrap();
+
+        let mid = LEN as u64 / 2;
+
+        b.iter(|| {
+            vf.insert_slice(mid, &slice);
+            vf.remove_range(mid..mid + slice.len() as u64);
+        });
+    }
+
+    #[bench]
+    fn iter(b: &mut Bencher) {
+        const LEN: usize = 1 << 16;
+        let mut vf = VecFile::new();
+        vf.resize(LEN as u64, &1u8).unwrap();
+
+        b.iter(|| {
+            assert_eq!(vf.iter().map(u64::from).sum::<u64>(), LEN as u64);
+        });
+    }
+
+    #[bench]
+    fn iter_unbuffered(b: &mut Bencher) {
+        const LEN: usize = 1 << 16;
+        let mut vf = VecFile::new();
+        vf.resize(LEN as u64, &1u8).unwrap();
+
+        b.iter(|| {
+            // A block of a single element, which is how the iterator used to read
+            assert_eq!(vf.iter().with_buffer_size(0).map(u64::from).sum::<u64>(), LEN as u64);
+        });
+    }
+
 }
-        
@@ -0,0 +1,404 @@
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use desse::{Desse, DesseSized};
+use crate::vec_file::{VecFile, Error, ReadMode, tested_temp};
+use crate::checksum::{DEFAULT_CHECKSUM_BLOCK_SIZE, MAX_CHECKSUM_BLOCK_SIZE};
+use crate::storage::Storage;
+use crate::health::{Event, Observer};
+
+/// Options for creating or opening a VecFile, similar to std::fs::OpenOptions.
+///
+/// By default an existing file is opened for reading and writing, and is expected to already be
+/// a VecFile. Files that are created start with a capacity of 8 elements and no shadows.
+///
+/// The storage that's used for the file and its shadows is picked by the type of VecFile that's
+/// being built, so `VecFile<T, MmapStorage>` memory maps the file for example.
+///
+/// ## Example
+/// ```
+/// use vecfile::*;
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let mut vf: VecFile<u32> = VecFileBuilder::new()
+///                                 .create(true)
+///                                 .capacity(1024)
+///                                 .shadows(1)
+///                                 .open(dir.path().join("data.bin"))
+///                                 .unwrap();
+/// vf.push(&8);
+/// assert_eq!(vf.cap(), 1024);
+/// ```
+#[derive(Debug, Clone)]
+pub struct VecFileBuilder {
+    read_only: bool,
+    create: bool,
+    create_new: bool,
+    truncate: bool,
+    capacity: u64,
+    shadows: usize,
+    shadow_dir: Option<PathBuf>,
+    temp_dir: Option<PathBuf>,
+    type_tag: Option<u64>,
+    checksums: bool,
+    checksum_block_size: u64,
+    read_mode: ReadMode,
+    observer: Option<Observer>,
+}
+
+impl VecFileBuilder {
+
+    /// Creates a builder with the default options.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Opens the file read only. Any method that would modify the VecFile returns an error.
+    ///
+    /// This can't be combined with create, create_new or truncate.
+    pub fn read_only(&mut self, read_only: bool) -> &mut Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Creates the file if it doesn't exist yet.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Creates the file, failing if it already exists.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Discards the contents of the file if it already exists.
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// The number of elements that the file is allocated for up front. Existing files that are
+    /// smaller are grown to fit it.
+    pub fn capacity(&mut self, capacity: u64) -> &mut Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// The number of shadows that are created alongside the file.
+    pub fn shadows(&mut self, shadows: usize) -> &mut Self {
+        self.shadows = shadows;
+        self
+    }
+
+    /// The directory that shadows are created in, if not the temp directory.
+    pub fn shadow_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.shadow_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// The directory that temporary files are created in, if not the system's temp directory.
+    /// This covers the file made by build, clones, and shadows when there's no shadow_dir.
+    pub fn temp_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.temp_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// The type tag that the file is created with, or that an existing file is expected to have.
+    /// Defaults to `VecFile::<T>::default_type_tag()`.
+    pub fn type_tag(&mut self, type_tag: u64) -> &mut Self {
+        self.type_tag = Some(type_tag);
+        self
+    }
+
+    /// Checksums the elements of files that are created, so that corruption is caught rather
+    /// than read back as elements. Each block of elements has a checksum, which is checked
+    /// whenever any of the block is read and updated whenever it's written.
+    ///
+    /// A block that doesn't match its checksum fails the read like any other I/O error, so the
+    /// file is replaced with a shadow if it has one, otherwise Error::ChecksumMismatch is
+    /// returned. Files that are opened keep whatever they were created with.
+    pub fn checksums(&mut self, checksums: bool) -> &mut Self {
+        self.checksums = checksums;
+        self
+    }
+
+    /// The size of the blocks that elements are checksummed in, in bytes. Defaults to 4096.
+    /// Smaller blocks make small reads and writes cheaper, at the cost of more checksums.
+    pub fn checksum_block_size(&mut self, block_size: u64) -> &mut Self {
+        self.checksum_block_size = block_size;
+        self
+    }
+
+    /// Which copies the VecFile's reads are served from, see ReadMode.
+    pub fn read_mode(&mut self, read_mode: ReadMode) -> &mut Self {
+        self.read_mode = read_mode;
+        self
+    }
+
+    /// A callback that's passed each of the VecFile's events, see VecFile::set_observer. It's
+    /// set before any shadows are made, so it sees them being made too.
+    pub fn observer<F>(&mut self, observer: F) -> &mut Self
+    where F: Fn(&Event) + Send + Sync + 'static {
+        self.observer = Some(Observer(Arc::new(observer)));
+        self
+    }
+
+    /// Opens or creates the VecFile at the given path with these options. Any named shadows the
+    /// file has are reopened along with it, unless it's opened read only.
+    ///
+    /// This returns an error if the VecFile's storage can't be backed by a file.
+    pub fn open<T, S, H, P>(&self, path: P) -> Result<VecFile<T, S, H>, Box<dyn std::error::Error>>
+    where T: Desse + DesseSized,
+          S: Storage,
+          H: Storage,
+          P: AsRef<Path> {
+
+        if self.read_only && (self.create || self.create_new || self.truncate) {
+            return Err(Error::ReadOnly.into());
+        }
+
+        let file = OpenOptions::new()
+                    .read(true)
+                    .write(!self.read_only)
+                    .create(self.create)
+                    .create_new(self.create_new)
+                    .truncate(self.truncate)
+                    .open(&path)?;
+        self.open_storage(S::from_file(file, self.read_only)?, Some(path.as_ref()))
+    }
+
+    /// Opens the VecFile that's in the given storage with these options, or creates one if the
+    /// storage is empty. The create and truncate options are ignored.
+    pub fn open_in<T, S, H>(&self, storage: S)
+        -> Result<VecFile<T, S, H>, Box<dyn std::error::Error>>
+    where T: Desse + DesseSized,
+          S: Storage,
+          H: Storage {
+        self.open_storage(storage, None)
+    }
+
+    /// Opens the VecFile that's in the given storage, which was opened from path if there is one.
+    fn open_storage<T, S, H>(&self, storage: S, path: Option<&Path>)
+        -> Result<VecFile<T, S, H>, Box<dyn std::error::Error>>
+    where T: Desse + DesseSized,
+          S: Storage,
+          H: Storage {
+
+        let type_tag = self.type_tag.unwrap_or_else(VecFile::<T, S, H>::default_type_tag);
+        let mut vf = if storage.size()? == 0 && !self.read_only {
+            // Either the file was just created or there's nothing in it, start it fresh.
+            VecFile::init(storage, self.capacity, type_tag, self.block_size()?)?
+        }
+        else {
+            VecFile::open_storage(storage, Some(type_tag))?
+        };
+        vf.read_only = self.read_only;
+        let mut vf = self.finish(vf)?;
+        if !self.read_only {
+            vf.reopen_shadows(path)?;
+        }
+        Ok(vf)
+    }
+
+    /// Creates a VecFile in temporary storage with these options. The open mode options are
+    /// ignored since the file is always new.
+    pub fn build<T, S, H>(&self) -> Result<VecFile<T, S, H>, Box<dyn std::error::Error>>
+    where T: Desse + DesseSized,
+          S: Storage,
+          H: Storage {
+        self.build_in(tested_temp(self.temp_dir.as_deref())?)
+    }
+
+    /// Creates a VecFile in the given storage with these options, overwriting whatever it held.
+    /// The open mode options are ignored since the file is always new.
+    pub fn build_in<T, S, H>(&self, storage: S)
+        -> Result<VecFile<T, S, H>, Box<dyn std::error::Error>>
+    where T: Desse + DesseSized,
+          S: Storage,
+          H: Storage {
+
+        if self.read_only {
+            return Err(Error::ReadOnly.into());
+        }
+
+        let type_tag = self.type_tag.unwrap_or_else(VecFile::<T, S, H>::default_type_tag);
+        self.finish(VecFile::init(storage, self.capacity, type_tag, self.block_size()?)?)
+    }
+
+    /// The checksum block size that new files are created with, which is 0 without checksums.
+    fn block_size(&self) -> Result<u64, Error> {
+        match self.checksum_block_size {
+            _ if !self.checksums => Ok(0),
+            block_size @ 1..=MAX_CHECKSUM_BLOCK_SIZE => Ok(block_size),
+            block_size => Err(Error::InvalidChecksumBlockSize(block_size)),
+        }
+    }
+
+    /// Applies the options that are shared by open and build.
+    fn finish<T, S, H>(&self, mut vf: VecFile<T, S, H>)
+        -> Result<VecFile<T, S, H>, Box<dyn std::error::Error>>
+    where T: Desse + DesseSized,
+          S: Storage,
+          H: Storage {
+        vf.temp_dir = self.temp_dir.clone();
+        vf.shadow_dir = self.shadow_dir.clone();
+        vf.read_mode = self.read_mode;
+        vf.copies_mut().stats.observer = self.observer.clone();
+
+        if !vf.is_read_only() && vf.cap() < self.capacity {
+            vf.reserve(self.capacity - vf.len())?;
+        }
+        vf.add_shadows(self.shadows)?;
+        Ok(vf)
+    }
+}
+
+impl Default for VecFileBuilder {
+    fn default() -> Self {
+        Self {
+            read_only: false,
+            create: false,
+            create_new: false,
+            truncate: false,
+            capacity: 8,
+            shadows: 0,
+            shadow_dir: None,
+            temp_dir: None,
+            type_tag: None,
+            checksums: false,
+            checksum_block_size: DEFAULT_CHECKSUM_BLOCK_SIZE,
+            read_mode: ReadMode::Primary,
+            observer: None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use crate::storage::MmapStorage;
+
+    #[test]
+    fn open_modes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("open_modes.bin");
+
+        // Nothing exists yet, so only creating should work
+        assert!(VecFileBuilder::new().open::<u16, File, File, _>(&path).is_err());
+        let mut vf: VecFile<u16> = VecFileBuilder::new().create_new(true).open(&path).unwrap();
+        vf.extend_from_slice(&[1, 2, 3]);
+        std::mem::drop(vf);
+        assert!(VecFileBuilder::new().create_new(true).open::<u16, File, File, _>(&path).is_err());
+
+        // Creating an existing file opens it as is
+        let vf: VecFile<u16> = VecFileBuilder::new().create(true).open(&path).unwrap();
+        assert_eq!(vf, vec![1, 2, 3]);
+        std::mem::drop(vf);
+
+        let vf: VecFile<u16> = VecFileBuilder::new().truncate(true).open(&path).unwrap();
+        assert!(vf.is_empty());
+    }
+
+    #[test]
+    fn read_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("read_only.bin");
+        let mut vf: VecFile<u16> = VecFile::new_with_path(&path).unwrap();
+        vf.extend_from_slice(&[1, 2, 3]);
+        std::mem::drop(vf);
+
+        assert!(VecFileBuilder::new().read_only(true).truncate(true).open::<u16, File, File, _>(&path).is_err());
+        let mut vf: VecFile<u16> = VecFileBuilder::new().read_only(true).open(&path).unwrap();
+        assert!(vf.is_read_only());
+        assert_eq!(vf.get(1), 2);
+        assert_eq!(vf, vec![1, 2, 3]);
+
+        match vf.try_push(&4).unwrap_err().downcast_ref::<Error>() {
+            Some(Error::ReadOnly) => (),
+            e => panic!("Unexpected result: {:?}", e),
+        }
+        assert!(vf.try_pop().is_err());
+        assert!(vf.try_set(0, &5).is_err());
+        assert!(vf.try_truncate(0).is_err());
+        assert!(vf.repair().is_err());
+        assert!(vf.scrub().is_clean());
+        assert_eq!(vf.len(), 3);
+        assert_eq!(vf, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn capacity_and_shadows() {
+        let dir = tempfile::tempdir().unwrap();
+        let shadow_dir = tempfile::tempdir().unwrap();
+
+        let mut vf: VecFile<u64> = VecFileBuilder::new()
+                                        .capacity(0)
+                                        .shadows(2)
+                                        .temp_dir(dir.path())
+                                        .shadow_dir(shadow_dir.path())
+                                        .build()
+                                        .unwrap();
+        assert_eq!(vf.cap(), 0);
+        for i in 0..20 {
+            vf.push(&i);
+        }
+        assert_eq!(vf.len(), 20);
+        assert!(vf.confirm_shadow_equivalence().unwrap());
+
+        let vf: VecFile<u64> = VecFileBuilder::new().capacity(100).build().unwrap();
+        assert_eq!(vf.cap(), 100);
+    }
+
+    #[test]
+    fn storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("storage.bin");
+        let mut vec: Vec<u32> = Vec::new();
+        let mut vf: VecFile<u32, MmapStorage, Vec<u8>> = VecFileBuilder::new()
+                                                            .create(true)
+                                                            .capacity(2)
+                                                            .shadows(1)
+                                                            .open(&path)
+                                                            .unwrap();
+
+        // Enough pushes to remap a few times
+        for i in 0..100 {
+            vf.push(&i);
+            vec.push(i);
+        }
+        vf.set(10, &1000);
+        vec[10] = 1000;
+        assert_eq!(vf.pop(), vec.pop().unwrap());
+        assert_eq!(vf.get(10), 1000);
+        assert_eq!(vf, vec);
+        assert!(vf.confirm_shadow_equivalence().unwrap());
+        assert_eq!(vf.clone(), vec);
+        vf.sync().unwrap();
+        std::mem::drop(vf);
+
+        let vf: VecFile<u32, MmapStorage> = VecFileBuilder::new().read_only(true).open(&path).unwrap();
+        assert_eq!(vf.len(), 99);
+        assert_eq!(vf.try_get(98).unwrap(), 98);
+        assert_eq!(vf, vec);
+
+        // Storage that can't be opened from a path can still be opened directly
+        assert!(VecFileBuilder::new().open::<u32, Vec<u8>, Vec<u8>, _>(&path).is_err());
+        let bytes = std::fs::read(&path).unwrap();
+        let vf: VecFile<u32, Vec<u8>> = VecFileBuilder::new().open_in(bytes).unwrap();
+        assert_eq!(vf, vec);
+        let vf: VecFile<u32, Vec<u8>> = VecFileBuilder::new().build_in(vec![1, 2, 3]).unwrap();
+        assert!(vf.is_empty());
+    }
+
+    #[test]
+    fn missing_temp_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        assert!(VecFileBuilder::new().temp_dir(&missing).build::<u8, File, File>().is_err());
+        assert!(VecFileBuilder::new().shadow_dir(&missing).shadows(1).build::<u8, File, File>().is_err());
+    }
+}
@@ -0,0 +1,471 @@
+use std::io;
+use std::ops::Range;
+use crate::header::HEADER_SIZE;
+use crate::storage::Storage;
+use crate::vec_file::Error;
+
+/// The size of the blocks that elements are checksummed in, unless another one is given
+pub(crate) const DEFAULT_CHECKSUM_BLOCK_SIZE: u64 = 4096;
+
+/// The largest block size a file can be checksummed with, since whole blocks are read at once
+pub(crate) const MAX_CHECKSUM_BLOCK_SIZE: u64 = 1024 * 1024;
+
+/// The size of each checksum, in bytes
+const CHECKSUM_SIZE: u64 = 4;
+
+/// How many checksums set_len moves or writes at once
+const CHECKSUMS_PER_CHUNK: u64 = 16 * 1024;
+
+/// The most bytes of old checksums set_len zeroes at once
+const ZEROES_PER_CHUNK: u64 = 64 * 1024;
+
+/// How the elements of a VecFile are checksummed, if they are.
+///
+/// The elements are split into blocks of block_size bytes, counting from the end of the header,
+/// and each block has a CRC32C checksum. The checksums are stored little endian directly after
+/// the last element the file has room for, so they move whenever the file is resized. Only the
+/// elements are checksummed, not the header or the checksums themselves.
+///
+/// Reads check every block they touch, and a block that doesn't match its checksum fails the
+/// read with Error::ChecksumMismatch wrapped in an io::Error, the same as any other failed read.
+/// Writes update the checksums of every block they touch, after checking the parts of those
+/// blocks they don't overwrite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Checksums {
+    pub block_size: u64, // 0 if there are no checksums
+    pub data_len: u64, // The number of bytes of elements, which is cap elements' worth
+}
+
+impl Checksums {
+    /// No checksums, storage is read and written as it is.
+    pub fn none() -> Self {
+        Self { block_size: 0, data_len: 0 }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.block_size != 0
+    }
+
+    /// The size of storage that holds the header, the elements and their checksums. This
+    /// saturates rather than overflowing, since no storage could be that large anyway.
+    pub fn storage_len(&self) -> u64 {
+        HEADER_SIZE.saturating_add(self.data_len)
+                   .saturating_add(self.block_count().saturating_mul(CHECKSUM_SIZE))
+    }
+
+    fn block_count(&self) -> u64 {
+        if self.is_enabled() { self.data_len.div_ceil(self.block_size) } else { 0 }
+    }
+
+    /// The bytes of the elements that the given block covers. The last block is cut short if the
+    /// elements end part way through it.
+    fn block_bounds(&self, block: u64) -> Range<u64> {
+        let start = block * self.block_size;
+        start..std::cmp::min(start + self.block_size, self.data_len)
+    }
+
+    /// The blocks that cover the given range of bytes of the elements.
+    fn blocks(&self, data: &Range<u64>) -> Range<u64> {
+        data.start / self.block_size..data.end.div_ceil(self.block_size)
+    }
+
+    /// The part of the given range of bytes of storage that's elements, relative to the start of
+    /// the elements, or None if there's no checksums or none of it is.
+    fn data_range(&self, offset: u64, len: usize) -> Option<Range<u64>> {
+        let start = std::cmp::max(offset, HEADER_SIZE);
+        let end = std::cmp::min(offset.saturating_add(len as u64), HEADER_SIZE + self.data_len);
+        if self.is_enabled() && start < end {
+            Some(start - HEADER_SIZE..end - HEADER_SIZE)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Fills buf with the bytes starting at offset in storage, checking any elements it covers
+    /// against their checksums.
+    pub fn read_exact_at<St: Storage>(&self, storage: &St, buf: &mut [u8], offset: u64)
+        -> io::Result<()> {
+        let data = match self.data_range(offset, buf.len()) {
+            Some(data) => data,
+            None => return storage.read_exact_at(buf, offset),
+        };
+
+        // Whatever's before or after the elements is read as it is
+        let data_start = (HEADER_SIZE + data.start - offset) as usize;
+        let data_end = (HEADER_SIZE + data.end - offset) as usize;
+        storage.read_exact_at(&mut buf[..data_start], offset)?;
+        storage.read_exact_at(&mut buf[data_end..], HEADER_SIZE + data.end)?;
+
+        let blocks = self.blocks(&data);
+        let first = self.block_bounds(blocks.start).start;
+        let block_bytes = self.read_blocks(storage, blocks)?;
+        buf[data_start..data_end].copy_from_slice(
+            &block_bytes[(data.start - first) as usize..(data.end - first) as usize]
+        );
+        Ok(())
+    }
+
+    /// Writes all of bytes at offset in storage, updating the checksums of any elements it
+    /// covers.
+    pub fn write_all_at<St: Storage>(&self, storage: &mut St, bytes: &[u8], offset: u64)
+        -> io::Result<()> {
+        let data = match self.data_range(offset, bytes.len()) {
+            Some(data) => data,
+            None => return storage.write_all_at(bytes, offset),
+        };
+
+        // The blocks at either end may only be partly overwritten, so the rest of them has to be
+        // read to work out their new checksums
+        let blocks = self.blocks(&data);
+        let first = self.block_bounds(blocks.start);
+        let last = self.block_bounds(blocks.end - 1);
+        let single = blocks.end - blocks.start == 1;
+        let mut block_bytes = vec![0u8; (last.end - first.start) as usize];
+        if data.start > first.start || (single && data.end < first.end) {
+            let first_len = (first.end - first.start) as usize;
+            block_bytes[..first_len].copy_from_slice(
+                &self.read_blocks(storage, blocks.start..blocks.start + 1)?
+            );
+        }
+        if !single && data.end < last.end {
+            let last_start = (last.start - first.start) as usize;
+            block_bytes[last_start..].copy_from_slice(
+                &self.read_blocks(storage, blocks.end - 1..blocks.end)?
+            );
+        }
+
+        let data_start = (HEADER_SIZE + data.start - offset) as usize;
+        let data_end = (HEADER_SIZE + data.end - offset) as usize;
+        block_bytes[(data.start - first.start) as usize..(data.end - first.start) as usize]
+            .copy_from_slice(&bytes[data_start..data_end]);
+        let sums: Vec<u32> = blocks.clone()
+                                   .map(|block| {
+                                       let bounds = self.block_bounds(block);
+                                       let start = (bounds.start - first.start) as usize;
+                                       let end = (bounds.end - first.start) as usize;
+                                       crc32c(&block_bytes[start..end])
+                                   })
+                                   .collect();
+
+        // The checksums are written last, so a write that's cut short fails its check
+        storage.write_all_at(bytes, offset)?;
+        self.write_checksums(storage, blocks.start, &sums)
+    }
+
+    /// Resizes storage so it holds len bytes of header and elements, along with the checksums
+    /// of the elements. The checksums are moved to the new end of the elements, and any new
+    /// elements are zeroed.
+    ///
+    /// The checksums are moved and written a chunk at a time, so this only needs a bounded
+    /// amount of memory however large the file is, though it still reads and writes every
+    /// checksum.
+    pub fn set_len<St: Storage>(&self, storage: &mut St, len: u64) -> io::Result<()> {
+        if !self.is_enabled() {
+            return storage.set_len(len);
+        }
+        let resized = self.resized(len);
+
+        // The blocks that are whole both before and after keep their checksums. The block after
+        // them is the only one whose contents carry over while its checksum changes, so it's
+        // checked before anything moves.
+        let kept = std::cmp::min(self.data_len, resized.data_len) / self.block_size;
+        let partial = if kept < self.block_count() {
+            self.read_blocks(storage, kept..kept + 1)?
+        }
+        else {
+            Vec::new()
+        };
+
+        if resized.data_len > self.data_len {
+            storage.set_len(resized.storage_len())?;
+            self.move_checksums(storage, &resized, kept)?;
+            // The old checksums now sit where new elements are, so they're zeroed like the rest
+            let old_end = self.storage_len() - HEADER_SIZE;
+            let stale = self.data_len..std::cmp::min(resized.data_len, old_end);
+            write_zeroes(storage, HEADER_SIZE + stale.start..HEADER_SIZE + stale.end)?;
+            resized.write_new_checksums(storage, kept, partial)
+        }
+        else {
+            // Shrinking moves the checksums down over elements that are being cut off, before
+            // the storage is truncated
+            self.move_checksums(storage, &resized, kept)?;
+            resized.write_new_checksums(storage, kept, partial)?;
+            storage.set_len(resized.storage_len())
+        }
+    }
+
+    /// Copies the checksums of the first kept blocks from where self keeps them to where to
+    /// keeps them, a chunk at a time. The two can overlap, so the chunks are copied from the
+    /// back when the checksums move up and from the front when they move down, which never
+    /// overwrites checksums that are yet to be copied.
+    fn move_checksums<St: Storage>(&self, storage: &mut St, to: &Self, kept: u64)
+        -> io::Result<()> {
+        if to.data_len == self.data_len {
+            return Ok(());
+        }
+        let chunks = kept.div_ceil(CHECKSUMS_PER_CHUNK);
+        for chunk in 0..chunks {
+            let chunk = if to.data_len > self.data_len { chunks - 1 - chunk } else { chunk };
+            let first = chunk * CHECKSUMS_PER_CHUNK;
+            let end = std::cmp::min(first + CHECKSUMS_PER_CHUNK, kept);
+            let sums = self.read_checksums(storage, first..end)?;
+            to.write_checksums(storage, first, &sums)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the checksums of the blocks from first onward, a chunk at a time, where the first
+    /// block starts with the bytes of partial and everything else is zeroed.
+    fn write_new_checksums<St: Storage>(&self, storage: &mut St, first: u64, mut partial: Vec<u8>)
+        -> io::Result<()> {
+        let zeroes = vec![0u8; self.block_size as usize];
+        let zeroed_sum = crc32c(&zeroes);
+        let mut sum = |block: u64| {
+            let bounds = self.block_bounds(block);
+            let block_len = (bounds.end - bounds.start) as usize;
+            if block == first {
+                partial.resize(block_len, 0);
+                crc32c(&partial)
+            }
+            else if block_len == zeroes.len() {
+                zeroed_sum
+            }
+            else {
+                crc32c(&zeroes[..block_len])
+            }
+        };
+
+        let blocks = self.block_count();
+        let mut chunk = first;
+        while chunk < blocks {
+            let end = std::cmp::min(chunk + CHECKSUMS_PER_CHUNK, blocks);
+            let sums: Vec<u32> = (chunk..end).map(&mut sum).collect();
+            self.write_checksums(storage, chunk, &sums)?;
+            chunk = end;
+        }
+        Ok(())
+    }
+
+    /// The checksums for the storage once set_len has resized it to len bytes.
+    pub fn resized(&self, len: u64) -> Self {
+        Self {
+            data_len: if self.is_enabled() { len - HEADER_SIZE } else { 0 },
+            ..*self
+        }
+    }
+
+    /// Reads the given blocks, checking each one against its checksum.
+    fn read_blocks<St: Storage>(&self, storage: &St, blocks: Range<u64>) -> io::Result<Vec<u8>> {
+        let first = self.block_bounds(blocks.start).start;
+        let last = self.block_bounds(blocks.end - 1).end;
+        let mut block_bytes = vec![0u8; (last - first) as usize];
+        storage.read_exact_at(&mut block_bytes, HEADER_SIZE + first)?;
+
+        let sums = self.read_checksums(storage, blocks.clone())?;
+        for (block, sum) in blocks.zip(sums) {
+            let bounds = self.block_bounds(block);
+            let start = (bounds.start - first) as usize;
+            let end = (bounds.end - first) as usize;
+            if crc32c(&block_bytes[start..end]) != sum {
+                return Err(mismatch(block));
+            }
+        }
+        Ok(block_bytes)
+    }
+
+    fn read_checksums<St: Storage>(&self, storage: &St, blocks: Range<u64>)
+        -> io::Result<Vec<u32>> {
+        let mut buf = vec![0u8; ((blocks.end - blocks.start) * CHECKSUM_SIZE) as usize];
+        storage.read_exact_at(&mut buf, self.checksum_offset(blocks.start))?;
+        Ok(buf.chunks_exact(CHECKSUM_SIZE as usize)
+              .map(|sum| u32::from_le_bytes([sum[0], sum[1], sum[2], sum[3]]))
+              .collect())
+    }
+
+    /// Writes sums as the checksums of the blocks starting at first.
+    fn write_checksums<St: Storage>(&self, storage: &mut St, first: u64, sums: &[u32])
+        -> io::Result<()> {
+        let buf: Vec<u8> = sums.iter().flat_map(|sum| sum.to_le_bytes()).collect();
+        storage.write_all_at(&buf, self.checksum_offset(first))
+    }
+
+    fn checksum_offset(&self, block: u64) -> u64 {
+        HEADER_SIZE + self.data_len + block * CHECKSUM_SIZE
+    }
+}
+
+/// Zeroes the given range of storage, a chunk at a time.
+fn write_zeroes<St: Storage>(storage: &mut St, range: Range<u64>) -> io::Result<()> {
+    let zeroes = vec![0u8; std::cmp::min(ZEROES_PER_CHUNK, range.end.saturating_sub(range.start))
+                               as usize];
+    let mut offset = range.start;
+    while offset < range.end {
+        let len = std::cmp::min(zeroes.len() as u64, range.end - offset);
+        storage.write_all_at(&zeroes[..len as usize], offset)?;
+        offset += len;
+    }
+    Ok(())
+}
+
+/// The error for a block that doesn't match its checksum. It's an io::Error so that it's
+/// handled like any other failed read, such as by replacing the storage with a shadow.
+fn mismatch(block: u64) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, Error::ChecksumMismatch { block })
+}
+
+/// The CRC32C (Castagnoli) lookup table, for the reflected polynomial
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0x82F6_3B78 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Computes the CRC32C of the given bytes.
+pub(crate) fn crc32c(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC32C_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksummed(block_size: u64, data_len: u64) -> (Checksums, Vec<u8>) {
+        let mut storage = vec![0u8; HEADER_SIZE as usize];
+        let checksums = Checksums { block_size, data_len: 0 };
+        checksums.set_len(&mut storage, HEADER_SIZE + data_len).unwrap();
+        (checksums.resized(HEADER_SIZE + data_len), storage)
+    }
+
+    fn mismatched_block(result: io::Result<()>) -> u64 {
+        let e = result.unwrap_err();
+        match e.get_ref().and_then(|inner| inner.downcast_ref::<Error>()) {
+            Some(Error::ChecksumMismatch { block }) => *block,
+            _ => panic!("Not a checksum mismatch: {}", e),
+        }
+    }
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(crc32c(b""), 0);
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+    }
+
+    #[test]
+    fn read_write() {
+        let (checksums, mut storage) = checksummed(16, 100);
+        assert_eq!(storage.len() as u64, HEADER_SIZE + 100 + 7 * 4);
+
+        // Writes that start and end part way through blocks, and that reach into the header
+        let bytes: Vec<u8> = (0..50).collect();
+        checksums.write_all_at(&mut storage, &bytes, HEADER_SIZE + 10).unwrap();
+        checksums.write_all_at(&mut storage, &[7; 8], HEADER_SIZE - 4).unwrap();
+        checksums.write_all_at(&mut storage, &[9; 2], HEADER_SIZE + 33).unwrap();
+
+        let mut expected = vec![0u8; 100];
+        expected[10..60].copy_from_slice(&bytes);
+        expected[..4].copy_from_slice(&[7; 4]);
+        expected[33..35].copy_from_slice(&[9; 2]);
+        let mut buf = vec![0u8; 100];
+        checksums.read_exact_at(&storage, &mut buf, HEADER_SIZE).unwrap();
+        assert_eq!(buf, expected);
+
+        let mut buf = vec![0u8; 5];
+        checksums.read_exact_at(&storage, &mut buf, HEADER_SIZE + 31).unwrap();
+        assert_eq!(buf, expected[31..36]);
+    }
+
+    #[test]
+    fn corruption() {
+        let (checksums, mut storage) = checksummed(16, 100);
+        storage[(HEADER_SIZE + 40) as usize] ^= 1;
+
+        // Only the block that was corrupted fails, even when reading part of it
+        let mut buf = vec![0u8; 4];
+        checksums.read_exact_at(&storage, &mut buf, HEADER_SIZE + 28).unwrap();
+        let result = checksums.read_exact_at(&storage, &mut buf, HEADER_SIZE + 44);
+        assert_eq!(mismatched_block(result), 2);
+
+        // Overwriting part of the block doesn't hide the corruption
+        let result = checksums.write_all_at(&mut storage, &[1], HEADER_SIZE + 33);
+        assert_eq!(mismatched_block(result), 2);
+
+        // But overwriting all of it does
+        checksums.write_all_at(&mut storage, &[1; 16], HEADER_SIZE + 32).unwrap();
+        checksums.read_exact_at(&storage, &mut buf, HEADER_SIZE + 44).unwrap();
+    }
+
+    #[test]
+    fn resize() {
+        let (mut checksums, mut storage) = checksummed(16, 40);
+        let bytes: Vec<u8> = (1..=40).collect();
+        checksums.write_all_at(&mut storage, &bytes, HEADER_SIZE).unwrap();
+
+        let mut written = bytes.len();
+        for &len in &[40, 41, 48, 100, 1000, 100, 33, 32, 0, 20] {
+            checksums.set_len(&mut storage, HEADER_SIZE + len).unwrap();
+            checksums = checksums.resized(HEADER_SIZE + len);
+            assert_eq!(storage.len() as u64, checksums.storage_len());
+
+            // What was kept is still there, and everything after it is zeroed
+            let mut buf = vec![0u8; len as usize];
+            checksums.read_exact_at(&storage, &mut buf, HEADER_SIZE).unwrap();
+            let kept = std::cmp::min(len as usize, written);
+            assert_eq!(buf[..kept], bytes[..kept]);
+            assert!(buf[kept..].iter().all(|&byte| byte == 0));
+
+            written = std::cmp::min(len as usize, bytes.len());
+            checksums.write_all_at(&mut storage, &bytes[..written], HEADER_SIZE).unwrap();
+        }
+
+        // A corrupt block that's carried over is caught rather than given a new checksum
+        storage[HEADER_SIZE as usize + 17] ^= 1;
+        let result = checksums.set_len(&mut storage, HEADER_SIZE + 100);
+        assert_eq!(mismatched_block(result), 1);
+    }
+
+    #[test]
+    fn resize_in_chunks() {
+        // Enough blocks that the checksums are moved and written in several chunks
+        let len = 2 * CHECKSUMS_PER_CHUNK + 100;
+        let (mut checksums, mut storage) = checksummed(2, len);
+        let bytes: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        checksums.write_all_at(&mut storage, &bytes, HEADER_SIZE).unwrap();
+
+        for &new_len in &[len + 3, 3 * len, len - 1, 3] {
+            checksums.set_len(&mut storage, HEADER_SIZE + new_len).unwrap();
+            checksums = checksums.resized(HEADER_SIZE + new_len);
+            let mut buf = vec![0u8; new_len as usize];
+            checksums.read_exact_at(&storage, &mut buf, HEADER_SIZE).unwrap();
+            let kept = std::cmp::min(new_len, len) as usize;
+            assert_eq!(buf[..kept], bytes[..kept]);
+            assert!(buf[kept..].iter().all(|&byte| byte == 0));
+            checksums.write_all_at(&mut storage, &bytes[..kept], HEADER_SIZE).unwrap();
+        }
+    }
+
+    #[test]
+    fn disabled() {
+        let checksums = Checksums::none();
+        let mut storage = Vec::new();
+        checksums.set_len(&mut storage, HEADER_SIZE + 10).unwrap();
+        assert_eq!(storage.len() as u64, HEADER_SIZE + 10);
+        checksums.write_all_at(&mut storage, &[1, 2], HEADER_SIZE).unwrap();
+        assert_eq!(storage[HEADER_SIZE as usize..HEADER_SIZE as usize + 3], [1, 2, 0]);
+    }
+}
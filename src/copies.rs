@@ -0,0 +1,398 @@
+use std::path::{Path, PathBuf};
+use crate::storage::Storage;
+use crate::header::{manifest_to_bytes, MANIFEST_OFFSET};
+use crate::checksum::Checksums;
+use crate::health::{Event, Stats};
+use crate::vec_file::{Error, rw_test, tested_temp_with};
+
+/// The size of the chunks that storage is copied in
+pub(crate) const COPY_BUF_SIZE: usize = 64 * 1024;
+
+/// The storage that currently holds the primary copy of a VecFile's data. It starts out as the
+/// storage the VecFile was created with, and becomes a shadow once one has had to replace it.
+pub(crate) enum Primary<S, H> {
+    Original(S),
+    Shadow(H),
+}
+
+impl<S: Storage, H: Storage> Primary<S, H> {
+    pub fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        match self {
+            Primary::Original(storage) => storage.read_exact_at(buf, offset),
+            Primary::Shadow(storage) => storage.read_exact_at(buf, offset),
+        }
+    }
+
+    pub fn size(&self) -> std::io::Result<u64> {
+        match self {
+            Primary::Original(storage) => storage.size(),
+            Primary::Shadow(storage) => storage.size(),
+        }
+    }
+
+    pub fn sync(&mut self) -> std::io::Result<()> {
+        match self {
+            Primary::Original(storage) => storage.sync(),
+            Primary::Shadow(storage) => storage.sync(),
+        }
+    }
+
+    /// Reads through the given checksums, see Checksums::read_exact_at.
+    pub fn read_checked(&self, checksums: &Checksums, buf: &mut [u8], offset: u64)
+        -> std::io::Result<()> {
+        match self {
+            Primary::Original(storage) => checksums.read_exact_at(storage, buf, offset),
+            Primary::Shadow(storage) => checksums.read_exact_at(storage, buf, offset),
+        }
+    }
+
+    /// Writes through the given checksums, see Checksums::write_all_at.
+    pub fn write_checked(&mut self, checksums: &Checksums, buf: &[u8], offset: u64)
+        -> std::io::Result<()> {
+        match self {
+            Primary::Original(storage) => checksums.write_all_at(storage, buf, offset),
+            Primary::Shadow(storage) => checksums.write_all_at(storage, buf, offset),
+        }
+    }
+
+    /// Resizes through the given checksums, see Checksums::set_len.
+    pub fn set_len_checked(&mut self, checksums: &Checksums, len: u64) -> std::io::Result<()> {
+        match self {
+            Primary::Original(storage) => checksums.set_len(storage, len),
+            Primary::Shadow(storage) => checksums.set_len(storage, len),
+        }
+    }
+}
+
+
+/// A copy of the primary that's kept in sync with it.
+pub(crate) struct Shadow<H> {
+    pub storage: H,
+    pub path: Option<PathBuf>, // Where the shadow lives if it was named, so it can be reopened
+}
+
+impl<H> Shadow<H> {
+    fn unnamed(storage: H) -> Self {
+        Self { storage, path: None }
+    }
+}
+
+
+/// A VecFile's primary storage along with the shadows that are kept in sync with it. This is
+/// where shadows replace the primary when it fails.
+///
+/// Anything that may need to create a shadow takes the directory to create it in.
+///
+/// The paths of named shadows are listed in the header's manifest, which is rewritten whenever
+/// a named shadow is added or removed. Named shadows that couldn't be reopened stay listed, so
+/// they're tried again the next time the file is opened.
+///
+/// Every copy keeps its own checksums, if there are any. A block that fails its checksum is
+/// treated the same as a failed read, so a corrupt primary is replaced by a shadow.
+///
+/// Failures along the way are counted in stats and passed to its observer, see notify.
+pub(crate) struct Copies<S, H> {
+    pub primary: Primary<S, H>,
+    pub shadows: Vec<Shadow<H>>,
+    pub checksums: Checksums, // How the elements of every copy are checksummed
+    pub stats: Stats,
+    pub primary_path: Option<PathBuf>, // Where the primary lives if it was opened from a path
+    pub missing: Vec<PathBuf>, // Named shadows that couldn't be reopened
+}
+
+impl<S: Storage, H: Storage> Copies<S, H> {
+    pub fn new(storage: S, checksums: Checksums) -> Self {
+        Self {
+            primary: Primary::Original(storage),
+            shadows: Vec::with_capacity(0),
+            checksums,
+            stats: Stats::default(),
+            primary_path: None,
+            missing: Vec::new(),
+        }
+    }
+
+    /// Runs f on the primary, replacing the primary with a shadow for as long as it fails. If
+    /// there's no shadows, the error is returned instead.
+    pub fn with_primary<R, F>(&mut self, shadow_dir: Option<&Path>, mut f: F)
+        -> Result<R, Box<dyn std::error::Error>>
+    where F: FnMut(&mut Primary<S, H>) -> std::io::Result<R> {
+        loop {
+            match f(&mut self.primary) {
+                Ok(ret) => break Ok(ret),
+                Err(e) if self.shadows.is_empty() => {
+                    self.notify(Event::Unrecovered { error: e.to_string() });
+                    break Err(into_error(e));
+                }
+                // The primary failed for some reason, replace it with one of its shadows
+                Err(e) => self.replace_with_shadow(e.to_string(), shadow_dir)?,
+            }
+        }
+    }
+
+    /// Reads from the primary, without replacing it if the read fails.
+    pub fn read_primary(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        self.primary.read_checked(&self.checksums, buf, offset)
+    }
+
+    /// Fills buf with the bytes starting at offset in the primary, replacing it with a shadow
+    /// for as long as the read fails.
+    pub fn read_exact_at(&mut self, buf: &mut [u8], offset: u64, shadow_dir: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>> {
+        let checksums = self.checksums;
+        self.with_primary(shadow_dir, |primary| primary.read_checked(&checksums, buf, offset))
+    }
+
+    /// Writes all of bytes at offset in the primary and all of the shadows. Any shadow that fails
+    /// the write is replaced, so this only fails if the primary does.
+    pub fn write_all_at(&mut self, bytes: &[u8], offset: u64, shadow_dir: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>> {
+        let checksums = self.checksums;
+        self.with_primary(shadow_dir, |primary| primary.write_checked(&checksums, bytes, offset))?;
+
+        let failed: Vec<_> = self.shadows.iter_mut()
+                                             .enumerate()
+                                             .filter_map(|(i, shadow)| {
+                                                 checksums.write_all_at(&mut shadow.storage,
+                                                                        bytes,
+                                                                        offset)
+                                                          .err()
+                                                          .map(|e| (i, e))
+                                             })
+                                             .collect();
+        self.replace_failed_shadows(&failed, shadow_dir)
+    }
+
+    /// Resizes the primary and all of the shadows to len bytes, plus the size of their
+    /// checksums if there are any.
+    pub fn set_len(&mut self, len: u64, shadow_dir: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>> {
+        let checksums = self.checksums;
+        self.with_primary(shadow_dir, |primary| primary.set_len_checked(&checksums, len))?;
+        // Any replacement shadows are copied from the primary, which has the new size now
+        self.checksums = checksums.resized(len);
+
+        // Any shadow failing to resize is having write issues
+        let failed: Vec<_> = self.shadows.iter_mut()
+                                             .enumerate()
+                                             .filter_map(|(i, shadow)| {
+                                                 checksums.set_len(&mut shadow.storage, len)
+                                                          .err()
+                                                          .map(|e| (i, e))
+                                             })
+                                             .collect();
+        // The replacements don't need to be resized like the others since they're fresh copies
+        // of the primary, which has already been resized.
+        self.replace_failed_shadows(&failed, shadow_dir)
+    }
+
+    /// Replaces the shadows at the given indices, which are having write issues, with fresh
+    /// copies of the primary. Each is given along with the error it failed with. If a
+    /// replacement can't be made, the failed shadow is still removed since it no longer matches
+    /// the primary.
+    ///
+    /// Replacements are always temporary, since a named shadow that's failed is likely on a disk
+    /// that's failing. This only returns an error if the manifest has to be updated and the
+    /// primary can't be written to.
+    pub fn replace_failed_shadows<E>(&mut self, failed: &[(usize, E)], shadow_dir: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>>
+    where E: std::fmt::Display {
+        // Remove them all up front, since making a new shadow can replace the primary with the
+        // last shadow, which would shift the indices.
+        let mut named_removed = false;
+        for (i, _) in failed.iter().rev() {
+            named_removed |= self.shadows.remove(*i).path.is_some();
+        }
+        if named_removed {
+            self.write_manifest(shadow_dir)?;
+        }
+
+        let mut replacing = true;
+        for (_, error) in failed {
+            if replacing {
+                match self.new_shadow(shadow_dir) {
+                    Ok(shadow) => self.shadows.push(Shadow::unnamed(shadow)),
+                    // If one can't be made, the rest likely can't either
+                    Err(_) => replacing = false,
+                }
+            }
+            self.notify(Event::ShadowFailed { error: error.to_string(), replaced: replacing });
+        }
+        Ok(())
+    }
+
+    /// Flushes the primary and all of the shadows. Any shadow that fails the flush is replaced,
+    /// so this only fails if the primary does.
+    pub fn sync(&mut self, shadow_dir: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+        self.with_primary(shadow_dir, |primary| primary.sync())?;
+
+        let failed: Vec<_> = self.shadows.iter_mut()
+                                             .enumerate()
+                                             .filter_map(|(i, shadow)| {
+                                                 shadow.storage.sync().err().map(|e| (i, e))
+                                             })
+                                             .collect();
+        // The replacements are temporary, so there's nothing for them to persist
+        self.replace_failed_shadows(&failed, shadow_dir)
+    }
+
+    pub fn add_shadows(&mut self, additional_shadows: usize, shadow_dir: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>> {
+        self.shadows.reserve(additional_shadows);
+        for _ in 0..additional_shadows {
+            let new_shadow = self.new_shadow(shadow_dir)?;
+            self.shadows.push(Shadow::unnamed(new_shadow));
+        }
+        Ok(())
+    }
+
+    /// Adds the given storage as a shadow, after copying the primary into it. The shadow is
+    /// named if it's given a path, though it's up to the caller to then write the manifest.
+    pub fn add_shadow(&mut self, shadow: H, path: Option<PathBuf>, shadow_dir: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>> {
+        let storage = self.sync_shadow(shadow, shadow_dir, false)?;
+        self.shadows.push(Shadow { storage, path });
+        Ok(())
+    }
+
+    /// Removes the given number of shadows, starting with the last one added.
+    pub fn remove_shadows(&mut self, count: usize, shadow_dir: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>> {
+        let keep = self.shadows.len().saturating_sub(count);
+        let named_removed = self.shadows.drain(keep..).any(|shadow| shadow.path.is_some());
+        if named_removed {
+            self.write_manifest(shadow_dir)?;
+        }
+        Ok(())
+    }
+
+    /// Serializes the manifest of named shadows, including the missing ones, along with an extra
+    /// path if one's given.
+    pub fn manifest(&self, extra: Option<&Path>) -> Result<Vec<u8>, Error> {
+        let paths = self.shadows.iter().filter_map(|shadow| shadow.path.as_deref());
+        // A missing shadow that's given as the extra path is being added back, so it's only
+        // listed once
+        let missing = self.missing.iter().map(|path| path.as_path())
+                                         .filter(|&path| Some(path) != extra);
+        manifest_to_bytes(paths.chain(missing).chain(extra))
+    }
+
+    /// Writes the paths of the current named shadows to the manifest in the header of the
+    /// primary and every shadow.
+    pub fn write_manifest(&mut self, shadow_dir: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>> {
+        let manifest = self.manifest(None)?;
+        self.write_all_at(&manifest, MANIFEST_OFFSET, shadow_dir)
+    }
+
+    /// Replaces the primary, which failed with the given error, with the last shadow.
+    pub fn replace_with_shadow(&mut self, error: String, shadow_dir: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>> {
+        let shadow = match self.shadows.pop() {
+            Some(shadow) => shadow,
+            // This should never happen, but if it does, that means no shadows exist to replace
+            // the primary, and this would only be called if the primary is no longer accessible.
+            // In such a case, we are in a very bad state, so panic.
+            None => panic!(
+                "This is a bug. Shadow replacement shouldn't occur when no shadows have been set"
+            ),
+        };
+
+        self.primary = Primary::Shadow(shadow.storage);
+        self.primary_path = shadow.path;
+        self.notify(Event::Failover { error });
+        if self.primary_path.is_some() {
+            // It's the primary now, so it's no longer listed as a shadow
+            self.write_manifest(shadow_dir)?;
+        }
+        self.add_shadows(1, shadow_dir)
+    }
+
+    /// Creates a new shadow of the primary
+    fn new_shadow(&mut self, shadow_dir: Option<&Path>) -> Result<H, Box<dyn std::error::Error>> {
+        let shadow = self.tested_shadow(shadow_dir)?;
+        self.sync_shadow(shadow, shadow_dir, true)
+    }
+
+    /// Continually generates temporary storage until one passes the read/write test, like
+    /// tested_temp, letting the observer know about each retry.
+    fn tested_shadow(&mut self, shadow_dir: Option<&Path>)
+        -> Result<H, Box<dyn std::error::Error>> {
+        let mut retries = Vec::new();
+        let shadow = tested_temp_with(shadow_dir, |e| retries.push(e.to_string()));
+        for error in retries {
+            self.notify(Event::ShadowRetry { error });
+        }
+        shadow
+    }
+
+    /// Copies the primary into the given shadow. If the shadow itself has issues, it's swapped
+    /// for a fresh temporary one when regenerate is set, otherwise an error is returned.
+    fn sync_shadow(&mut self, mut shadow: H, shadow_dir: Option<&Path>, regenerate: bool)
+        -> Result<H, Box<dyn std::error::Error>> {
+        let mut orig_read_fail_counter = 0;
+        let orig_read_fail_counter_max = 5;
+
+        while let Err(e) = self.copy_primary_to(&mut shadow) {
+            self.notify(Event::ShadowRetry { error: e.to_string() });
+            // The copy failed, so test the dest storage for writeability.
+            match rw_test(&mut shadow) {
+                Ok(()) => {
+                    // The destination is passing read/write tests still, so the issue
+                    // lies with the primary potentially.
+                    orig_read_fail_counter += 1;
+                    if orig_read_fail_counter == orig_read_fail_counter_max {
+                        // The primary has failed too many times.
+                        if self.shadows.is_empty() {
+                            // The destination is ok, so there's an issue with the primary,
+                            // and with no other shadows, the data is irrecoverable.
+                            let error = Error::IrrecoverableState;
+                            self.notify(Event::Unrecovered { error: error.to_string() });
+                            return Err(error.into());
+                        }
+                        else {
+                            // Replace the primary, and give the new one the same chances
+                            self.replace_with_shadow(e.to_string(), shadow_dir)?;
+                            orig_read_fail_counter = 0;
+                        }
+                    }
+                }
+                Err(_) if regenerate => {
+                    // Something happened to the tested temp storage between generation and
+                    // copying data over. Generate a new one.
+                    shadow = self.tested_shadow(shadow_dir)?;
+                    orig_read_fail_counter = 0;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(shadow)
+    }
+
+    /// Copies everything in the primary to dest, resizing dest to match. Any elements that
+    /// don't match their checksums fail the copy, rather than being copied over.
+    pub fn copy_primary_to<D: Storage>(&self, dest: &mut D) -> std::io::Result<()> {
+        let size = self.primary.size()?;
+        dest.set_len(size)?;
+
+        let mut buf = vec![0u8; COPY_BUF_SIZE];
+        let mut offset = 0;
+        while offset < size {
+            let chunk = std::cmp::min(size - offset, COPY_BUF_SIZE as u64) as usize;
+            self.read_primary(&mut buf[..chunk], offset)?;
+            dest.write_all_at(&buf[..chunk], offset)?;
+            offset += chunk as u64;
+        }
+        Ok(())
+    }
+}
+
+
+/// Unwraps the crate's Error from an io::Error if that's what it holds, such as for a checksum
+/// mismatch, so that it can be downcast to one.
+fn into_error(e: std::io::Error) -> Box<dyn std::error::Error> {
+    match e.downcast::<Error>() {
+        Ok(e) => e.into(),
+        Err(e) => e.into(),
+    }
+}
@@ -0,0 +1,242 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use crate::storage::Storage;
+
+/// Storage that wraps another and injects faults into it, for testing how a VecFile copes with
+/// its storage failing.
+///
+/// Faults are set through the handle returned by faults, which can be kept after the storage
+/// has been handed to a VecFile. Temporary storage, such as new shadows, starts without faults.
+///
+/// This is only available with the `testing` feature.
+///
+/// ## Example
+/// ```
+/// use vecfile::*;
+///
+/// let storage = FaultyStorage::new(Vec::new());
+/// let faults = storage.faults();
+/// let mut vf: VecFile<u32, FaultyStorage, Vec<u8>> = VecFileBuilder::new()
+///                                                        .shadows(1)
+///                                                        .build_in(storage)
+///                                                        .unwrap();
+/// vf.push(&1);
+///
+/// // The failing storage is replaced by its shadow, and a new shadow is made
+/// faults.fail_reads(true);
+/// assert_eq!(vf.get(0), 1);
+/// assert_eq!(vf.shadow_count(), 1);
+/// ```
+pub struct FaultyStorage<S = Vec<u8>> {
+    inner: S,
+    faults: Faults,
+}
+
+impl<S: Storage> FaultyStorage<S> {
+    /// Wraps the given storage, without any faults to start with.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            faults: Faults::default(),
+        }
+    }
+
+    /// Returns a handle for setting this storage's faults.
+    pub fn faults(&self) -> Faults {
+        self.faults.clone()
+    }
+
+    /// Returns the wrapped storage.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Storage> Storage for FaultyStorage<S> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        if self.faults.state().fail_reads.fire() {
+            return Err(fault("injected read fault"));
+        }
+        self.inner.read_exact_at(buf, offset)
+    }
+
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        let mut state = self.faults.state();
+        // Writes that fail don't count towards the corrupted one, since nothing was written
+        if state.fail_writes.fire() {
+            return Err(fault("injected write fault"));
+        }
+        if state.grows_past_max_size(&self.inner, offset.saturating_add(buf.len() as u64))? {
+            return Err(fault("no space left on device"));
+        }
+
+        if state.corrupt_writes.fire() {
+            let corrupted: Vec<u8> = buf.iter().map(|byte| !byte).collect();
+            self.inner.write_all_at(&corrupted, offset)
+        }
+        else {
+            self.inner.write_all_at(buf, offset)
+        }
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        let mut state = self.faults.state();
+        if state.fail_set_lens.fire() {
+            return Err(fault("injected set_len fault"));
+        }
+        if state.grows_past_max_size(&self.inner, len)? {
+            return Err(fault("no space left on device"));
+        }
+        self.inner.set_len(len)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.size()
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        if self.faults.state().fail_syncs.fire() {
+            return Err(fault("injected sync fault"));
+        }
+        self.inner.sync()
+    }
+
+    fn temp(dir: Option<&Path>) -> io::Result<Self> {
+        Ok(Self::new(S::temp(dir)?))
+    }
+
+    fn from_file(file: File, read_only: bool) -> io::Result<Self> {
+        Ok(Self::new(S::from_file(file, read_only)?))
+    }
+}
+
+
+/// A handle to the faults of a FaultyStorage. Clones of it share the same faults.
+///
+/// Faults for the nth operation count from when they're set, starting at 0 for the next one,
+/// and only happen once.
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl Faults {
+    /// Makes every read fail, or none of them.
+    pub fn fail_reads(&self, fail: bool) {
+        self.state().fail_reads = Trigger::from(fail);
+    }
+
+    /// Makes every write and resize fail, or none of them.
+    pub fn fail_writes(&self, fail: bool) {
+        let mut state = self.state();
+        state.fail_writes = Trigger::from(fail);
+        state.fail_set_lens = Trigger::from(fail);
+    }
+
+    /// Makes the nth read from now fail.
+    pub fn fail_nth_read(&self, n: u64) {
+        self.state().fail_reads = Trigger::After(n);
+    }
+
+    /// Makes the nth write from now fail.
+    pub fn fail_nth_write(&self, n: u64) {
+        self.state().fail_writes = Trigger::After(n);
+    }
+
+    /// Makes the nth resize from now fail.
+    pub fn fail_nth_set_len(&self, n: u64) {
+        self.state().fail_set_lens = Trigger::After(n);
+    }
+
+    /// Makes every sync fail, or none of them.
+    pub fn fail_syncs(&self, fail: bool) {
+        self.state().fail_syncs = Trigger::from(fail);
+    }
+
+    /// Makes the nth sync from now fail.
+    pub fn fail_nth_sync(&self, n: u64) {
+        self.state().fail_syncs = Trigger::After(n);
+    }
+
+    /// Makes the nth write from now succeed, but with every bit of what it writes flipped.
+    pub fn corrupt_nth_write(&self, n: u64) {
+        self.state().corrupt_writes = Trigger::After(n);
+    }
+
+    /// Makes writes and resizes that would grow the storage past max_size bytes fail, as if the
+    /// disk were full. None removes the limit.
+    pub fn set_max_size(&self, max_size: Option<u64>) {
+        self.state().max_size = max_size;
+    }
+
+    /// Removes every fault.
+    pub fn clear(&self) {
+        *self.state() = FaultState::default();
+    }
+
+    /// Locks the faults. A panic while they're locked can't leave them in an invalid state, so
+    /// poisoning is ignored.
+    fn state(&self) -> MutexGuard<'_, FaultState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Debug, Default)]
+struct FaultState {
+    fail_reads: Trigger,
+    fail_writes: Trigger,
+    fail_set_lens: Trigger,
+    fail_syncs: Trigger,
+    corrupt_writes: Trigger,
+    max_size: Option<u64>,
+}
+
+impl FaultState {
+    /// Returns whether growing the given storage to size bytes would take it past max_size.
+    /// Storage that's already past it can still be written to up to its current size.
+    fn grows_past_max_size<S: Storage>(&self, storage: &S, size: u64) -> io::Result<bool> {
+        match self.max_size {
+            Some(max_size) if size > max_size => Ok(size > storage.size()?),
+            _ => Ok(false),
+        }
+    }
+}
+
+/// Which operations a fault happens for.
+#[derive(Debug, Clone, Copy, Default)]
+enum Trigger {
+    #[default]
+    Never,
+    Always,
+    After(u64), // Once, after this many more operations
+}
+
+impl Trigger {
+    /// Counts an operation, returning true if the fault happens for it.
+    fn fire(&mut self) -> bool {
+        match *self {
+            Trigger::Never => false,
+            Trigger::Always => true,
+            Trigger::After(0) => {
+                *self = Trigger::Never;
+                true
+            }
+            Trigger::After(n) => {
+                *self = Trigger::After(n - 1);
+                false
+            }
+        }
+    }
+}
+
+impl From<bool> for Trigger {
+    fn from(always: bool) -> Self {
+        if always { Trigger::Always } else { Trigger::Never }
+    }
+}
+
+fn fault(msg: &str) -> io::Error {
+    io::Error::other(msg)
+}
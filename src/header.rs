@@ -0,0 +1,222 @@
+use std::path::{Path, PathBuf};
+use crate::vec_file::Error;
+use crate::checksum::MAX_CHECKSUM_BLOCK_SIZE;
+
+/// The header that sits at the front of every VecFile's underlying file. It describes the layout
+/// of the elements that follow it so that a file can be reopened by a later process.
+///
+/// All values are stored little endian, laid out as follows:
+///
+/// | offset | size | field         |
+/// |--------|------|---------------|
+/// | 0      | 8    | magic         |
+/// | 8      | 4    | version       |
+/// | 12     | 4    | reserved      |
+/// | 16     | 8    | element size  |
+/// | 24     | 8    | len           |
+/// | 32     | 8    | cap           |
+/// | 40     | 8    | type tag      |
+/// | 48     | 8    | checksum block size |
+/// | 56     | 8    | reserved      |
+/// | 64     | rest | shadow manifest |
+///
+/// The checksum block size is 0 if the elements aren't checksummed. Otherwise the checksums
+/// follow the last element the file has room for, see Checksums.
+///
+/// The shadow manifest lists the paths of the VecFile's named shadows so that they can be
+/// reopened along with it. It's a u32 count, followed by each path as a u32 length and that many
+/// bytes of UTF-8. Whatever's left of the header after it is zeroed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Header {
+    pub element_size: u64,
+    pub len: u64,
+    pub cap: u64,
+    pub type_tag: u64,
+    pub checksum_block_size: u64,
+}
+
+/// Identifies a file as a VecFile
+pub(crate) const MAGIC: [u8; 8] = *b"VECFILE\0";
+
+/// The current version of the on-disk format
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+/// The size of the header, in bytes. Elements start directly after it. This is a full page so that
+/// the elements stay page aligned and there's plenty of room for future fields.
+pub(crate) const HEADER_SIZE: u64 = 4096;
+
+const VERSION_OFFSET: usize = 8;
+const ELEMENT_SIZE_OFFSET: usize = 16;
+pub(crate) const LEN_OFFSET: u64 = 24;
+pub(crate) const CAP_OFFSET: u64 = 32;
+pub(crate) const TYPE_TAG_OFFSET: u64 = 40;
+const CHECKSUM_BLOCK_SIZE_OFFSET: usize = 48;
+pub(crate) const MANIFEST_OFFSET: u64 = 64;
+
+impl Header {
+    /// Serializes the header into a buffer that's HEADER_SIZE bytes long.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = vec![0u8; HEADER_SIZE as usize];
+        buf[..MAGIC.len()].copy_from_slice(&MAGIC);
+        put_u32(&mut buf, VERSION_OFFSET, FORMAT_VERSION);
+        put_u64(&mut buf, ELEMENT_SIZE_OFFSET, self.element_size);
+        put_u64(&mut buf, LEN_OFFSET as usize, self.len);
+        put_u64(&mut buf, CAP_OFFSET as usize, self.cap);
+        put_u64(&mut buf, TYPE_TAG_OFFSET as usize, self.type_tag);
+        put_u64(&mut buf, CHECKSUM_BLOCK_SIZE_OFFSET, self.checksum_block_size);
+        buf
+    }
+
+    /// Deserializes a header, checking that the magic and version are ones we understand.
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, Error> {
+        if buf.len() < HEADER_SIZE as usize || buf[..MAGIC.len()] != MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+
+        let version = get_u32(buf, VERSION_OFFSET);
+        if version != FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let header = Self {
+            element_size: get_u64(buf, ELEMENT_SIZE_OFFSET),
+            len: get_u64(buf, LEN_OFFSET as usize),
+            cap: get_u64(buf, CAP_OFFSET as usize),
+            type_tag: get_u64(buf, TYPE_TAG_OFFSET as usize),
+            checksum_block_size: get_u64(buf, CHECKSUM_BLOCK_SIZE_OFFSET),
+        };
+
+        if header.len > header.cap {
+            return Err(Error::LenExceedsCap(header.len, header.cap));
+        }
+        if header.checksum_block_size > MAX_CHECKSUM_BLOCK_SIZE {
+            return Err(Error::InvalidChecksumBlockSize(header.checksum_block_size));
+        }
+        Ok(header)
+    }
+}
+
+/// Serializes the shadow manifest, padded out to the end of the header so that it replaces any
+/// longer manifest that was there before.
+pub(crate) fn manifest_to_bytes<'a, I>(paths: I) -> Result<Vec<u8>, Error>
+where I: IntoIterator<Item=&'a Path> {
+    let mut buf = vec![0u8; 4];
+    let mut count = 0u32;
+    for path in paths {
+        let path_bytes = path.to_str()
+                             .ok_or_else(|| Error::InvalidShadowPath(path.to_path_buf()))?
+                             .as_bytes();
+        buf.extend_from_slice(&(path_bytes.len() as u32).to_le_bytes());
+        buf.extend_from_slice(path_bytes);
+        count += 1;
+    }
+    put_u32(&mut buf, 0, count);
+
+    let manifest_size = (HEADER_SIZE - MANIFEST_OFFSET) as usize;
+    if buf.len() > manifest_size {
+        return Err(Error::ManifestFull);
+    }
+    buf.resize(manifest_size, 0);
+    Ok(buf)
+}
+
+/// Deserializes the shadow manifest from a header.
+pub(crate) fn manifest_from_bytes(header: &[u8]) -> Result<Vec<PathBuf>, Error> {
+    let manifest = header.get(MANIFEST_OFFSET as usize..HEADER_SIZE as usize)
+                         .ok_or(Error::InvalidManifest)?;
+    let count = get_u32(manifest, 0);
+    let mut paths = Vec::new();
+    let mut offset = 4;
+    for _ in 0..count {
+        let len = manifest.get(offset..offset + 4).ok_or(Error::InvalidManifest)?;
+        let len = get_u32(len, 0) as usize;
+        offset += 4;
+        let path_bytes = offset.checked_add(len)
+                               .and_then(|end| manifest.get(offset..end))
+                               .ok_or(Error::InvalidManifest)?;
+        let path = std::str::from_utf8(path_bytes).map_err(|_| Error::InvalidManifest)?;
+        paths.push(PathBuf::from(path));
+        offset += len;
+    }
+    Ok(paths)
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut [u8], offset: usize, value: u64) {
+    buf[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+fn get_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn get_u64(buf: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(len: u64, checksum_block_size: u64) -> Header {
+        Header { element_size: 4, len, cap: 8, type_tag: 7, checksum_block_size }
+    }
+
+    #[test]
+    fn round_trip() {
+        let header = header(3, 64);
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), HEADER_SIZE as usize);
+        assert_eq!(Header::from_bytes(&bytes).unwrap(), header);
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut bytes = header(3, 0).to_bytes();
+        bytes[0] = b'X';
+        assert!(matches!(Header::from_bytes(&bytes), Err(Error::InvalidMagic)));
+
+        let mut bytes = header(3, 0).to_bytes();
+        put_u32(&mut bytes, VERSION_OFFSET, FORMAT_VERSION + 1);
+        assert!(matches!(Header::from_bytes(&bytes), Err(Error::UnsupportedVersion(_))));
+
+        let bytes = header(9, 0).to_bytes();
+        assert!(matches!(Header::from_bytes(&bytes), Err(Error::LenExceedsCap(9, 8))));
+
+        let bytes = header(3, u64::MAX).to_bytes();
+        assert!(matches!(Header::from_bytes(&bytes), Err(Error::InvalidChecksumBlockSize(_))));
+    }
+
+    #[test]
+    fn manifest() {
+        let mut bytes = header(3, 0).to_bytes();
+        assert!(manifest_from_bytes(&bytes).unwrap().is_empty());
+
+        let paths = [Path::new("/mnt/a/shadow.bin"), Path::new("/mnt/b/shadow.bin")];
+        let manifest = manifest_to_bytes(paths.iter().cloned()).unwrap();
+        bytes[MANIFEST_OFFSET as usize..].copy_from_slice(&manifest);
+        assert_eq!(manifest_from_bytes(&bytes).unwrap(), paths);
+        assert_eq!(Header::from_bytes(&bytes).unwrap().len, 3);
+
+        // A shorter manifest replaces a longer one entirely
+        let manifest = manifest_to_bytes(paths[..1].iter().cloned()).unwrap();
+        bytes[MANIFEST_OFFSET as usize..].copy_from_slice(&manifest);
+        assert_eq!(manifest_from_bytes(&bytes).unwrap(), &paths[..1]);
+
+        let long_path = "a".repeat(HEADER_SIZE as usize);
+        assert!(matches!(manifest_to_bytes(vec![Path::new(&long_path)]),
+                         Err(Error::ManifestFull)));
+
+        // Lengths that run past the end of the header
+        put_u32(&mut bytes, MANIFEST_OFFSET as usize + 4, u32::MAX);
+        assert!(matches!(manifest_from_bytes(&bytes), Err(Error::InvalidManifest)));
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use crate::storage::Storage;
+use crate::copies::Copies;
+use crate::scrub::CopyId;
+
+/// Something that happened to a VecFile's copies, see VecFile::set_observer. Most hold the error
+/// that caused them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The underlying file, or the shadow that had replaced it, failed and was replaced by a
+    /// shadow.
+    Failover { error: String },
+    /// A shadow failed a write or a resize and was removed. It's replaced by a fresh copy of the
+    /// file if one could be made.
+    ShadowFailed { error: String, replaced: bool },
+    /// Making a new shadow failed and was tried again, either because the temporary storage
+    /// failed its read/write test or because copying the file into it failed.
+    ShadowRetry { error: String },
+    /// The underlying file, or the shadow that had replaced it, failed with no shadows left to
+    /// replace it, so the error was returned.
+    Unrecovered { error: String },
+    /// A copy held the wrong bytes, or couldn't be read, and was overwritten with the right ones
+    /// starting at offset in the file. This happens when a read with ReadMode::Majority outvotes
+    /// it, or VecFile::repair fixes it.
+    CopyRepaired { copy: CopyId, offset: u64 },
+    /// A named shadow listed in the header couldn't be reopened along with the file. It's kept in
+    /// the header to be tried again next time, see VecFile::missing_shadow_paths.
+    ShadowMissing { path: PathBuf, error: String },
+}
+
+impl Event {
+    /// Returns the error that caused the event, if there was one.
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            Event::Failover { error } => Some(error),
+            Event::ShadowFailed { error, .. } => Some(error),
+            Event::ShadowRetry { error } => Some(error),
+            Event::Unrecovered { error } => Some(error),
+            Event::CopyRepaired { .. } => None,
+            Event::ShadowMissing { error, .. } => Some(error),
+        }
+    }
+}
+
+/// A snapshot of how a VecFile's copies have held up since it was created or opened, see
+/// VecFile::health.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Health {
+    /// The number of times the primary has been replaced by a shadow.
+    pub failovers: u64,
+    /// The number of shadows that have failed and been removed, see VecFile::failed_shadow_count.
+    pub failed_shadows: u64,
+    /// The number of times making a new shadow has had to be retried.
+    pub shadow_retries: u64,
+    /// The number of times a copy has been overwritten because it held the wrong bytes.
+    pub repaired_copies: u64,
+    /// The number of named shadows that couldn't be reopened along with the file.
+    pub missing_shadows: u64,
+    /// The error behind the most recent event, if there's been one.
+    pub last_error: Option<String>,
+    /// The number of shadows there are now. Each one is another copy that the file can fail
+    /// over to.
+    pub shadows: usize,
+}
+
+/// The callback that a VecFile's events are passed to.
+#[derive(Clone)]
+pub(crate) struct Observer(pub Arc<dyn Fn(&Event) + Send + Sync>);
+
+impl std::fmt::Debug for Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+/// What's happened to a VecFile's copies so far, along with who to tell about it.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    pub failovers: u64,
+    pub failed_shadows: u64,
+    pub shadow_retries: u64,
+    pub repaired_copies: u64,
+    pub missing_shadows: u64,
+    pub last_error: Option<String>,
+    pub observer: Option<Observer>,
+}
+
+
+impl<S: Storage, H: Storage> Copies<S, H> {
+    /// Counts the event and passes it on to the observer, and to the log if it's enabled.
+    pub(crate) fn notify(&mut self, event: Event) {
+        let stats = &mut self.stats;
+        match event {
+            Event::Failover { .. } => stats.failovers += 1,
+            Event::ShadowFailed { .. } => stats.failed_shadows += 1,
+            Event::ShadowRetry { .. } => stats.shadow_retries += 1,
+            Event::Unrecovered { .. } => (),
+            Event::CopyRepaired { .. } => stats.repaired_copies += 1,
+            Event::ShadowMissing { .. } => stats.missing_shadows += 1,
+        }
+        if let Some(error) = event.error() {
+            stats.last_error = Some(error.to_owned());
+        }
+
+        #[cfg(feature = "log")]
+        log_event(&event);
+        if let Some(Observer(observer)) = &stats.observer {
+            observer(&event);
+        }
+    }
+
+    pub(crate) fn health(&self) -> Health {
+        Health {
+            failovers: self.stats.failovers,
+            failed_shadows: self.stats.failed_shadows,
+            shadow_retries: self.stats.shadow_retries,
+            repaired_copies: self.stats.repaired_copies,
+            missing_shadows: self.stats.missing_shadows,
+            last_error: self.stats.last_error.clone(),
+            shadows: self.shadows.len(),
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+fn log_event(event: &Event) {
+    match event {
+        Event::Failover { error } => log::warn!("VecFile failed over to a shadow: {}", error),
+        Event::ShadowFailed { error, replaced: true } => {
+            log::warn!("VecFile shadow failed and was replaced: {}", error)
+        }
+        Event::ShadowFailed { error, replaced: false } => {
+            log::error!("VecFile shadow failed and couldn't be replaced: {}", error)
+        }
+        Event::ShadowRetry { error } => log::debug!("Retrying a new VecFile shadow: {}", error),
+        Event::Unrecovered { error } => {
+            log::error!("VecFile failed with no shadows to fail over to: {}", error)
+        }
+        Event::CopyRepaired { copy, offset } => {
+            log::warn!("VecFile repaired {:?} at offset {}", copy, offset)
+        }
+        Event::ShadowMissing { path, error } => {
+            log::error!("VecFile shadow {} couldn't be reopened: {}", path.display(), error)
+        }
+    }
+}
@@ -14,4 +14,21 @@
 /// }
 /// ```
 pub mod vec_file;
+pub mod builder;
+pub mod storage;
+#[cfg(any(test, feature = "testing"))]
+pub mod faulty;
+pub mod scrub;
+pub mod health;
+mod header;
+mod copies;
+mod checksum;
+#[cfg(feature = "rayon")]
+mod par;
 pub use crate::vec_file::*;
+pub use crate::builder::*;
+pub use crate::storage::*;
+#[cfg(any(test, feature = "testing"))]
+pub use crate::faulty::*;
+pub use crate::scrub::*;
+pub use crate::health::*;
@@ -0,0 +1,89 @@
+use std::convert::TryFrom;
+use desse::{Desse, DesseSized};
+use rayon::prelude::*;
+use crate::vec_file::VecFile;
+use crate::storage::Storage;
+
+/// Roughly how many bytes each of par_iter's chunks reads at once
+const PAR_ITER_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Parallel iteration, available with the rayon feature.
+///
+/// Each chunk is read with a single positioned read, so chunks can be read from any number of
+/// threads at once over the same file.
+///
+/// The chunks share the VecFile's storage rather than each opening a handle of their own. Reads
+/// only take the shared side of its lock and don't use the file's cursor, so they don't wait on
+/// each other, and sharing keeps chunks working for storage that has no path to reopen, such as
+/// temp files and `Vec<u8>`. It also means a chunk that fails to read fails over to a shadow like
+/// any other read, rather than failing on a handle the VecFile doesn't know about.
+impl<T, S, H> VecFile<T, S, H>
+where T: Desse + DesseSized + Send + Sync,
+      S: Storage + Send + Sync,
+      H: Storage + Send + Sync {
+
+    /// Returns a parallel iterator over the elements, in blocks of chunk_size elements. The last
+    /// block is shorter if the len isn't a multiple of chunk_size.
+    ///
+    /// This will panic if chunk_size is 0, or if a block can't be read, the same as iterating.
+    pub fn par_chunks(&self, chunk_size: usize)
+        -> impl IndexedParallelIterator<Item = Vec<T>> + '_ {
+        assert!(chunk_size != 0, "chunk_size must be non-zero");
+        let len = self.len();
+        let chunk_count = usize::try_from(len.div_ceil(chunk_size as u64))
+                                .expect("Too many chunks to index with a usize");
+
+        (0..chunk_count).into_par_iter().map(move |chunk| {
+            let start = chunk as u64 * chunk_size as u64;
+            let count = std::cmp::min(chunk_size as u64, len - start) as usize;
+            self.read_many(start, count).unwrap()
+        })
+    }
+
+    /// Returns a parallel iterator over the elements. This reads the elements in blocks, see
+    /// par_chunks.
+    ///
+    /// This will panic if an element can't be read, the same as iterating.
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = T> + '_ {
+        let chunk_size = std::cmp::max(1, PAR_ITER_CHUNK_BYTES / self.element_size());
+        self.par_chunks(chunk_size).flat_map_iter(|chunk| chunk)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MmapStorage;
+
+    #[test]
+    fn par_chunks() {
+        let vf: VecFile<u32> = (0..1000).collect();
+        let chunks: Vec<Vec<u32>> = vf.par_chunks(300).collect();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[3], (900..1000).collect::<Vec<_>>());
+        assert_eq!(chunks.concat(), (0..1000).collect::<Vec<_>>());
+
+        let empty: VecFile<u32> = VecFile::new();
+        assert_eq!(empty.par_chunks(10).count(), 0);
+    }
+
+    #[test]
+    fn par_iter() {
+        let vf: VecFile<u64, MmapStorage> = (0..100_000).collect();
+        assert_eq!(vf.par_iter().sum::<u64>(), (0..100_000).sum());
+        assert_eq!(vf.par_iter().filter(|e| e % 7 == 0).count(), 100_000 / 7 + 1);
+
+        let vf: VecFile<u64> = (0..100_000).collect();
+        let mut elements: Vec<u64> = vf.par_iter().collect();
+        elements.sort_unstable();
+        assert_eq!(vf, elements);
+    }
+
+    #[test]
+    #[should_panic]
+    fn par_chunks_zero() {
+        let vf: VecFile<u32> = (0..10).collect();
+        vf.par_chunks(0).count();
+    }
+}
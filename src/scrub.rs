@@ -0,0 +1,154 @@
+use std::path::Path;
+use crate::header::HEADER_SIZE;
+use crate::storage::Storage;
+use crate::copies::Copies;
+use crate::health::Event;
+
+/// One of a VecFile's copies of its elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CopyId {
+    /// The underlying file, or whichever shadow has replaced it.
+    Primary,
+    /// The shadow at the given index, counting in the order they were added.
+    Shadow(usize),
+}
+
+/// A block of elements that isn't the same in every copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockMismatch {
+    /// The index of the block, see ScrubReport::block_size.
+    pub block: u64,
+    /// The copies that hold the wrong bytes for the block, or that couldn't be read. If the block
+    /// isn't resolved, this is every copy that doesn't match the primary instead, or every copy
+    /// if the primary couldn't be read.
+    pub bad_copies: Vec<CopyId>,
+    /// Whether it's known which bytes are right. A copy's bytes are right if they match their
+    /// checksum, when there are checksums, and they're held by more than half of the copies
+    /// that are left.
+    pub resolved: bool,
+}
+
+/// What a scrub or repair found, see VecFile::scrub.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// The size of the blocks that were compared, in bytes. Block n holds the bytes of the
+    /// elements from n * block_size up to the next block, counting from the first element.
+    pub block_size: u64,
+    /// The number of blocks that were compared.
+    pub blocks: u64,
+    /// The blocks that differ between copies, in order.
+    pub mismatches: Vec<BlockMismatch>,
+}
+
+impl ScrubReport {
+    /// Returns true if every copy matched.
+    pub fn is_clean(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+
+impl<S: Storage, H: Storage> Copies<S, H> {
+    /// Compares the bytes of the given block across every copy, returning how they differ along
+    /// with the right bytes if they're known.
+    pub(crate) fn scrub_block(&self, block: u64, bytes: std::ops::Range<u64>)
+        -> Option<(BlockMismatch, Option<Vec<u8>>)> {
+        let len = (bytes.end - bytes.start) as usize;
+        let (right, bad_copies) = vote(self.read_copies(len, HEADER_SIZE + bytes.start));
+        if bad_copies.is_empty() {
+            return None;
+        }
+        let mismatch = BlockMismatch { block, bad_copies, resolved: right.is_some() };
+        Some((mismatch, right))
+    }
+
+    /// Reads len bytes at offset from every copy, checking them against their checksums if
+    /// there are any. Copies that fail the read have no bytes.
+    pub(crate) fn read_copies(&self, len: usize, offset: u64) -> Vec<(CopyId, Option<Vec<u8>>)> {
+        let read = |copy: CopyId| {
+            let mut buf = vec![0u8; len];
+            let result = match copy {
+                CopyId::Primary => self.read_primary(&mut buf, offset),
+                CopyId::Shadow(i) => {
+                    self.checksums.read_exact_at(&self.shadows[i].storage, &mut buf, offset)
+                }
+            };
+            result.ok().map(|_| buf)
+        };
+        std::iter::once(CopyId::Primary).chain((0..self.shadows.len()).map(CopyId::Shadow))
+                                        .map(|copy| (copy, read(copy)))
+                                        .collect()
+    }
+
+    /// Overwrites each of the given copies with bytes at offset. A shadow that fails the write
+    /// is replaced.
+    ///
+    /// The primary is treated as having failed if it's one of them, since it was outvoted or
+    /// couldn't be read, so it's replaced by a shadow that already holds the bytes rather than
+    /// being overwritten.
+    pub(crate) fn rewrite(&mut self, copies: &[CopyId], bytes: &[u8], offset: u64,
+                          shadow_dir: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>> {
+        let checksums = self.checksums;
+        let mut copies = copies.to_vec();
+        let mut rewrite_primary = false;
+        if copies.contains(&CopyId::Primary) {
+            // Shadows replace the primary from the back, so a right one is moved there first
+            let right = (0..self.shadows.len()).rev()
+                                               .find(|&i| !copies.contains(&CopyId::Shadow(i)));
+            match right {
+                Some(right) => {
+                    let last = self.shadows.len() - 1;
+                    self.shadows.swap(right, last);
+                    for copy in copies.iter_mut().filter(|copy| **copy == CopyId::Shadow(last)) {
+                        *copy = CopyId::Shadow(right);
+                    }
+                    let error = format!("Outvoted or unreadable at offset {}", offset);
+                    self.replace_with_shadow(error, shadow_dir)?;
+                }
+                // Without a shadow to take over, the primary is overwritten instead
+                None => rewrite_primary = true,
+            }
+        }
+
+        let mut failed = Vec::new();
+        for &copy in &copies {
+            if let CopyId::Shadow(i) = copy {
+                match checksums.write_all_at(&mut self.shadows[i].storage, bytes, offset) {
+                    Ok(()) => self.notify(Event::CopyRepaired { copy, offset }),
+                    Err(e) => failed.push((i, e)),
+                }
+            }
+        }
+        self.replace_failed_shadows(&failed, shadow_dir)?;
+
+        if rewrite_primary {
+            self.with_primary(shadow_dir, |primary| {
+                primary.write_checked(&checksums, bytes, offset)
+            })?;
+            self.notify(Event::CopyRepaired { copy: CopyId::Primary, offset });
+        }
+        Ok(())
+    }
+}
+
+/// Works out which of the bytes read from each copy are right, which are whichever bytes more
+/// than half of the readable copies hold. Returns the right bytes if there are any, along with
+/// the copies that don't hold them. If there aren't any, it's the copies that don't match the
+/// primary instead, or every copy if the primary couldn't be read.
+pub(crate) fn vote(copies: Vec<(CopyId, Option<Vec<u8>>)>) -> (Option<Vec<u8>>, Vec<CopyId>) {
+    let readable: Vec<&Vec<u8>> = copies.iter().filter_map(|(_, bytes)| bytes.as_ref()).collect();
+    let right = readable.iter()
+                        .find(|&&bytes| {
+                            let votes = readable.iter().filter(|&&other| other == bytes).count();
+                            votes * 2 > readable.len()
+                        })
+                        .map(|&bytes| bytes.clone());
+
+    let expected = right.as_ref().or(copies[0].1.as_ref());
+    let bad_copies = copies.iter()
+                           .filter(|(_, bytes)| expected.is_none() || bytes.as_ref() != expected)
+                           .map(|&(copy, _)| copy)
+                           .collect();
+    (right, bad_copies)
+}
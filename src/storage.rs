@@ -0,0 +1,359 @@
+use std::fs::File;
+use std::path::Path;
+use std::convert::TryFrom;
+use memmap2::{MmapMut, MmapOptions};
+
+/// Somewhere a VecFile, or one of its shadows, keeps its bytes.
+///
+/// All reads and writes are positioned, so a storage doesn't need to track a cursor. Implemented
+/// for File, for `Vec<u8>` as an in-memory store, and for MmapStorage. FaultyStorage wraps any of
+/// them to inject I/O errors for testing.
+pub trait Storage: Sized {
+    /// Fills buf with the bytes starting at offset. Reading past the end is an error.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()>;
+
+    /// Writes all of buf starting at offset, growing the storage if it isn't large enough.
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<()>;
+
+    /// Grows or shrinks the storage to len bytes. Any added bytes are zeroed.
+    fn set_len(&mut self, len: u64) -> std::io::Result<()>;
+
+    /// Returns the current length of the storage in bytes.
+    fn size(&self) -> std::io::Result<u64>;
+
+    /// Makes sure any buffered writes have reached wherever the storage is persisted.
+    fn sync(&mut self) -> std::io::Result<()>;
+
+    /// Creates new, empty storage that only lives as long as the returned value. This is what
+    /// shadows, clones and VecFileBuilder::build use. The directory is where it should be created
+    /// if that means anything for the storage, and otherwise is ignored.
+    fn temp(dir: Option<&Path>) -> std::io::Result<Self>;
+
+    /// Creates storage that's backed by the given file, which was opened for reading, and for
+    /// writing unless read_only is set. This is what VecFileBuilder::open uses.
+    ///
+    /// Storage that can't be backed by a file returns an Unsupported error, which is the default.
+    fn from_file(file: File, read_only: bool) -> std::io::Result<Self> {
+        let _ = (file, read_only);
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+
+/// Reads and writes are positional (pread and pwrite on unix), so they never touch the file's
+/// cursor and any number of readers can share the file.
+impl Storage for File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        file_read_exact_at(self, buf, offset)
+    }
+
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<()> {
+        file_write_all_at(self, buf, offset)
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        File::set_len(self, len)
+    }
+
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.sync_data()
+    }
+
+    fn temp(dir: Option<&Path>) -> std::io::Result<Self> {
+        match dir {
+            Some(dir) => tempfile::tempfile_in(dir),
+            None => tempfile::tempfile(),
+        }
+    }
+
+    fn from_file(file: File, _read_only: bool) -> std::io::Result<Self> {
+        Ok(file)
+    }
+}
+
+
+/// Keeps everything in memory, which is mostly useful for tests, or as a fast shadow that only
+/// protects against the primary failing.
+impl Storage for Vec<u8> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        buf.copy_from_slice(slice_range(self, offset, buf.len())?);
+        Ok(())
+    }
+
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<()> {
+        let end = offset.checked_add(buf.len() as u64).ok_or(std::io::ErrorKind::InvalidInput)?;
+        if end > self.len() as u64 {
+            Storage::set_len(self, end)?;
+        }
+        slice_range_mut(self, offset, buf.len())?.copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        let len = usize::try_from(len).map_err(|_| std::io::ErrorKind::OutOfMemory)?;
+        self.resize(len, 0);
+        Ok(())
+    }
+
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn temp(_dir: Option<&Path>) -> std::io::Result<Self> {
+        Ok(Vec::new())
+    }
+}
+
+
+/// A file that's accessed through a memory map, so reads and writes are memory copies rather
+/// than syscalls. The file is remapped whenever it's resized.
+///
+/// Note that I/O errors on a memory mapped file are raised by the OS as signals (SIGBUS on unix)
+/// rather than returned as errors, so shadows can't protect it in that case. Nothing else should
+/// truncate the file while it's mapped for the same reason.
+pub struct MmapStorage {
+    file: File,
+    map: Option<MmapMut>, // None while the file is empty, since an empty file can't be mapped
+    read_only: bool,
+}
+
+impl MmapStorage {
+    /// Maps the file into memory. This has to be done again whenever the file is resized.
+    fn remap(&mut self) -> std::io::Result<()> {
+        // Drop the old mapping before creating the new one
+        self.map = None;
+        if self.file.metadata()?.len() > 0 {
+            let map = unsafe {
+                if self.read_only {
+                    // A private mapping, since a shared one can't be writable without write access
+                    // to the file. It's never written to since the VecFile is read only.
+                    MmapOptions::new().map_copy(&self.file)?
+                }
+                else {
+                    MmapMut::map_mut(&self.file)?
+                }
+            };
+            self.map = Some(map);
+        }
+        Ok(())
+    }
+}
+
+impl Storage for MmapStorage {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+        let map = self.map.as_deref().unwrap_or(&[]);
+        buf.copy_from_slice(slice_range(map, offset, buf.len())?);
+        Ok(())
+    }
+
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<()> {
+        let end = offset.checked_add(buf.len() as u64).ok_or(std::io::ErrorKind::InvalidInput)?;
+        if end > self.size()? {
+            self.set_len(end)?;
+        }
+        let map = self.map.as_deref_mut().unwrap_or(&mut []);
+        slice_range_mut(map, offset, buf.len())?.copy_from_slice(buf);
+        Ok(())
+    }
+
+    fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+        // Unmap first so the mapping never covers bytes that have been truncated away, and since
+        // Windows won't resize a file while it's mapped
+        self.map = None;
+        if let Err(e) = self.file.set_len(len) {
+            // Map the file at whatever length it's been left at, so it's still usable
+            let _ = self.remap();
+            return Err(e);
+        }
+        self.remap()
+    }
+
+    fn size(&self) -> std::io::Result<u64> {
+        Ok(self.map.as_ref().map(|map| map.len() as u64).unwrap_or(0))
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        match &self.map {
+            Some(map) if !self.read_only => map.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    fn temp(dir: Option<&Path>) -> std::io::Result<Self> {
+        Self::from_file(File::temp(dir)?, false)
+    }
+
+    fn from_file(file: File, read_only: bool) -> std::io::Result<Self> {
+        let mut storage = Self {
+            file,
+            map: None,
+            read_only,
+        };
+        storage.remap()?;
+        Ok(storage)
+    }
+}
+
+
+#[cfg(unix)]
+fn file_read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(unix)]
+fn file_write_all_at(file: &File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+// Windows' positional reads and writes still move the cursor, which is fine since nothing here
+// depends on it. They can also be short, so they're looped like read_exact and write_all.
+#[cfg(windows)]
+fn file_read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn file_write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> std::io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_write(buf, offset) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// Anywhere else falls back to seeking, so concurrent reads of the same file can get in each
+// other's way there.
+#[cfg(not(any(unix, windows)))]
+fn file_read_exact_at(mut file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn file_write_all_at(mut file: &File, buf: &[u8], offset: u64) -> std::io::Result<()> {
+    use std::io::{Write, Seek, SeekFrom};
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(buf)
+}
+
+/// Returns the len bytes of buf starting at offset, or an error if they're out of its range.
+fn slice_range(buf: &[u8], offset: u64, len: usize) -> std::io::Result<&[u8]> {
+    usize::try_from(offset).ok()
+        .and_then(|start| buf.get(start..start.checked_add(len)?))
+        .ok_or_else(|| std::io::ErrorKind::UnexpectedEof.into())
+}
+
+/// Returns the len bytes of buf starting at offset, or an error if they're out of its range.
+fn slice_range_mut(buf: &mut [u8], offset: u64, len: usize) -> std::io::Result<&mut [u8]> {
+    usize::try_from(offset).ok()
+        .and_then(move |start| buf.get_mut(start..start.checked_add(len)?))
+        .ok_or_else(|| std::io::ErrorKind::WriteZero.into())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<S: Storage>(mut storage: S) {
+        assert_eq!(storage.size().unwrap(), 0);
+        storage.write_all_at(&[1, 2, 3, 4], 2).unwrap();
+        assert_eq!(storage.size().unwrap(), 6);
+
+        let mut buf = [0u8; 6];
+        storage.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, [0, 0, 1, 2, 3, 4]);
+        assert!(storage.read_exact_at(&mut buf, 1).is_err());
+
+        storage.set_len(3).unwrap();
+        storage.set_len(5).unwrap();
+        let mut buf = [0u8; 5];
+        storage.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, [0, 0, 1, 0, 0]);
+        storage.sync().unwrap();
+    }
+
+    #[test]
+    fn backends() {
+        round_trip(File::temp(None).unwrap());
+        round_trip(Vec::<u8>::temp(None).unwrap());
+        round_trip(MmapStorage::temp(None).unwrap());
+    }
+
+    // Windows' positional reads and writes move the cursor, so this only holds on unix
+    #[cfg(unix)]
+    #[test]
+    fn file_cursor() {
+        use std::io::{Seek, Write};
+
+        let mut file = File::temp(None).unwrap();
+        file.write_all(&[1, 2, 3]).unwrap();
+        file.write_all_at(&[4, 5], 1).unwrap();
+        let mut buf = [0u8; 3];
+        file.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, [1, 4, 5]);
+        assert!(file.read_exact_at(&mut buf, 1).is_err());
+
+        // Positioned reads and writes leave the cursor where it was
+        assert_eq!(file.stream_position().unwrap(), 3);
+    }
+
+    #[test]
+    fn read_only_mmap() {
+        let mut file = File::temp(None).unwrap();
+        file.write_all_at(&[7, 8, 9], 0).unwrap();
+
+        let mut storage = MmapStorage::from_file(file.try_clone().unwrap(), true).unwrap();
+        let mut buf = [0u8; 3];
+        storage.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, [7, 8, 9]);
+        storage.sync().unwrap();
+
+        assert!(Vec::<u8>::from_file(file, false).is_err());
+    }
+
+    #[test]
+    fn failed_mmap_resize() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("failed_mmap_resize.bin");
+        std::fs::write(&path, [7, 8, 9]).unwrap();
+
+        // Resizing a file that isn't open for writing fails, which shouldn't lose the mapping
+        let file = File::open(&path).unwrap();
+        let mut storage = MmapStorage::from_file(file, true).unwrap();
+        assert!(storage.set_len(6).is_err());
+        assert_eq!(storage.size().unwrap(), 3);
+        let mut buf = [0u8; 3];
+        storage.read_exact_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, [7, 8, 9]);
+    }
+}
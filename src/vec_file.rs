@@ -1,14 +1,27 @@
-use std::io::{Read, Write, Seek, SeekFrom};
 use desse::{Desse, DesseSized};
 use std::fs::{File, OpenOptions};
 use std::marker::PhantomData;
-use std::cell::Cell;
-use std::mem::transmute;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, PoisonError};
+use std::path::{Path, PathBuf};
+use std::convert::TryFrom;
+use std::ops::{Bound, RangeBounds};
+use crate::header::{Header, HEADER_SIZE, LEN_OFFSET, CAP_OFFSET, TYPE_TAG_OFFSET};
+use crate::header::{manifest_from_bytes, manifest_to_bytes, MANIFEST_OFFSET};
+use crate::builder::VecFileBuilder;
+use crate::storage::Storage;
+use crate::copies::{Copies, Primary, COPY_BUF_SIZE};
+use crate::checksum::{Checksums, DEFAULT_CHECKSUM_BLOCK_SIZE, MAX_CHECKSUM_BLOCK_SIZE};
+use crate::scrub::{ScrubReport, vote};
+use crate::health::{Event, Health, Observer};
 
 /// A file that can be treated similarly to a Vec. By default the underlying file is a temporary
 /// file which is requested from the operating system, but there's options to used path'd files as
 /// well.
 ///
+/// The underlying file can be any Storage, given by S, such as an in-memory `Vec<u8>` or a memory
+/// mapped MmapStorage. Shadows use the storage given by H, which is the same as S unless
+/// otherwise specified.
+///
 /// Has optionally "shadowing" which syncs 1 or more files which can automatically replace the
 /// original in case of IO or other types of errors. Once a shadow is used to replace the original,
 /// another one is automatically generated and synced, so if there's 3 shadows there will always be
@@ -23,56 +36,202 @@ use std::mem::transmute;
 /// than return an Err. 
 /// If shadows aren't being used, the try methods may be used to avoid errors.
 ///
+/// A VecFile is Send and Sync as long as T and its storage are, which they are for the storage
+/// in this crate. Reads through a shared reference, like get and iteration, can be done from any
+/// number of threads at once. Only a read that fails and has to replace the file with a shadow
+/// blocks the others.
+///
 /// Note: Index and IndexMut are not implemented since they require returning references, and we
 /// cannot get a reference from a section of a file.
-pub struct VecFile<T: Desse + DesseSized> {
-    file: Cell<File>, // The underlying file 
-    shadows: Cell<Vec<File>>, // The shadows that may exist
+pub struct VecFile<T: Desse + DesseSized, S: Storage = File, H: Storage = S> {
+    copies: RwLock<Copies<S, H>>, // The underlying storage and the shadows that may exist
     len: u64, // The current number of elements in the file
     cap: u64, // The max number of elements the file can hold at its given allocated lenght
-    _phantom: PhantomData<*const T>, // Phantom data for the generic type parameter
+    type_tag: u64, // Identifies the type T that the file holds
+    pub(crate) read_only: bool, // Whether the underlying file was opened read only
+    pub(crate) temp_dir: Option<PathBuf>, // Where temporary files are created, if not the default
+    pub(crate) shadow_dir: Option<PathBuf>, // Where shadows are created, if not the temp_dir
+    pub(crate) read_mode: ReadMode, // Which copies reads are served from
+    _phantom: PhantomData<T>, // Phantom data for the generic type parameter
 }
 
 
 impl<T: Desse + DesseSized> VecFile<T> {
 
-     
-    // Note: At the end of every public method, the file should be seek'd to the pos of where a new
-    // element should be written to.
-   
     /// Creates a new empty VecFile.
     pub fn new() -> Self {
         Default::default()
     }
 
-    /// This creates a new VecFile that points at a file with the given path. 
+    /// This creates a new VecFile that points at a file with the given path.
     /// NOTE: This truncates the file.
-    pub fn new_with_path<P: AsRef<std::path::Path>>(path: P) 
+    ///
+    /// See VecFileBuilder for more control over how the file is opened.
+    pub fn new_with_path<P: AsRef<Path>>(path: P)
+        -> Result<Self, Box<dyn std::error::Error>> {
+        VecFileBuilder::new().create(true).truncate(true).open(path)
+    }
+
+    /// Reopens a VecFile that was previously created at the given path.
+    ///
+    /// The header at the front of the file is validated and used to restore the len and cap of
+    /// the collection. This will return an error if the file isn't a VecFile, was written by an
+    /// unsupported version, or holds elements of a different size or type tag than T.
+    ///
+    /// The file is expected to have T's default type tag, see open_with_type_tag if it was given
+    /// a different one.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        VecFileBuilder::new().open(path)
+    }
+
+    /// Reopens a VecFile that was previously created at the given path, expecting the file to
+    /// have the given type tag.
+    ///
+    /// This will return an error if the file's type tag doesn't match, in addition to the errors
+    /// returned by open.
+    pub fn open_with_type_tag<P: AsRef<Path>>(path: P, type_tag: u64)
+        -> Result<Self, Box<dyn std::error::Error>> {
+        VecFileBuilder::new().type_tag(type_tag).open(path)
+    }
+
+    /// Reopens a VecFile that was previously created at the given path, without checking that it
+    /// was created for T.
+    ///
+    /// # Safety
+    ///
+    /// The file's type tag is ignored, so the elements in the file are reinterpreted as T as long
+    /// as they have the same size. It's up to the caller to make sure that's valid for T.
+    pub unsafe fn open_unchecked<P: AsRef<Path>>(path: P)
+        -> Result<Self, Box<dyn std::error::Error>> {
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let mut vf = Self::open_storage(file, None)?;
+        vf.reopen_shadows(Some(path.as_ref()))?;
+        Ok(vf)
+    }
+}
+
+
+impl<T: Desse + DesseSized, S: Storage, H: Storage> VecFile<T, S, H> {
+
+    // Note: All reads and writes go through read_exact_at and write_all_at with an explicit
+    // offset, so nothing depends on where the storage happens to be seek'd to.
+
+    /// Validates the header of the given storage and restores the VecFile it describes. The type
+    /// tag is only checked if one is expected.
+    pub(crate) fn open_storage(storage: S, type_tag: Option<u64>)
+        -> Result<Self, Box<dyn std::error::Error>> {
+
+        let storage_len = storage.size()?;
+        let mut buf = vec![0u8; std::cmp::min(storage_len, HEADER_SIZE) as usize];
+        storage.read_exact_at(&mut buf, 0)?;
+        let header = Header::from_bytes(&buf)?;
+
+        let element_size = std::mem::size_of::<<T as Desse>::Output>() as u64;
+        if header.element_size != element_size {
+            return Err(Error::ElementSizeMismatch(header.element_size, element_size).into());
+        }
+
+        match type_tag {
+            Some(type_tag) if type_tag != header.type_tag =>
+                return Err(Error::TypeTagMismatch(header.type_tag, type_tag).into()),
+            _ => (),
+        }
+
+        // The storage must be large enough to hold every element the header claims it can, along
+        // with their checksums
+        let data_len = header.cap.checked_mul(element_size)
+                                 .filter(|size| size.checked_add(HEADER_SIZE).is_some())
+                                 .ok_or(Error::IndexExceedsMaxU64)?;
+        let checksums = Checksums { block_size: header.checksum_block_size, data_len };
+        let required_len = checksums.storage_len();
+        if storage_len < required_len {
+            return Err(Error::FileTooShort(storage_len, required_len).into());
+        }
+
+        let mut vf = unsafe { Self::from_raw_parts(storage, header.len, header.cap) };
+        vf.type_tag = header.type_tag;
+        vf.copies_mut().checksums = checksums;
+        Ok(vf)
+    }
+
+    /// Writes a fresh header to the given storage and sizes it to hold cap elements. The
+    /// elements are checksummed in blocks of checksum_block_size bytes, unless it's 0.
+    pub(crate) fn init(mut storage: S, cap: u64, type_tag: u64, checksum_block_size: u64)
         -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self { // TODO: Replace with default
-            file: 
-                OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?.into(),
-            shadows: Vec::with_capacity(0).into(),
+        let header = Header {
+            element_size: std::mem::size_of::<<T as Desse>::Output>() as u64,
             len: 0,
-            cap: 8,
-            _phantom: PhantomData,
-        })
+            cap,
+            type_tag,
+            checksum_block_size,
+        };
+        let len = cap.checked_mul(header.element_size)
+                     .and_then(|size| size.checked_add(HEADER_SIZE))
+                     .ok_or(Error::IndexExceedsMaxU64)?;
+        storage.write_all_at(&header.to_bytes(), 0)?;
+        // Cut it down to just the header first, so the elements start out zeroed to match the
+        // checksums
+        let checksums = Checksums { block_size: checksum_block_size, data_len: 0 };
+        if checksums.is_enabled() {
+            storage.set_len(HEADER_SIZE)?;
+        }
+        checksums.set_len(&mut storage, len)?;
+
+        let mut vf = unsafe { Self::from_raw_parts(storage, 0, cap) };
+        vf.type_tag = type_tag;
+        vf.copies_mut().checksums = checksums.resized(len);
+        Ok(vf)
     }
 
     /// Creates a VecFile instance with the given parts
     ///
+    /// # Safety
+    ///
     /// This is considered unsafe since there's no checks or guarantees that the reconstructed
     /// VecFile has the given len or cap or if the underlying data is valid data for the given type
-    /// T.
-    pub unsafe fn from_raw_parts(file: File, len: u64, cap: u64) -> Self {
+    /// T. The storage is expected to start with a VecFile header, with elements directly after it.
+    /// The header's checksums aren't kept up to date, so it shouldn't have any.
+    pub unsafe fn from_raw_parts(storage: S, len: u64, cap: u64) -> Self {
         Self {
-            file: file.into(),
-            shadows: Vec::with_capacity(0).into(),
+            copies: RwLock::new(Copies::new(storage, Checksums::none())),
             len,
             cap,
+            type_tag: Self::default_type_tag(),
+            read_only: false,
+            temp_dir: None,
+            shadow_dir: None,
+            read_mode: ReadMode::Primary,
             _phantom: PhantomData,
         }
     }
+
+    /// Returns the type tag that's derived from T, which is what new files are tagged with unless
+    /// another one is set.
+    ///
+    /// This is a hash of `std::any::type_name::<T>()`, which isn't guaranteed to be stable between
+    /// compiler versions, so files that need to outlive a toolchain upgrade should be given their
+    /// own tag with set_type_tag.
+    pub fn default_type_tag() -> u64 {
+        fnv1a(std::any::type_name::<T>().as_bytes())
+    }
+
+    /// Get the type tag that's stored in the file's header
+    pub fn type_tag(&self) -> u64 {
+        self.type_tag
+    }
+
+    /// Returns whether the elements are checksummed, see VecFileBuilder::checksums.
+    pub fn has_checksums(&self) -> bool {
+        self.copies().checksums.is_enabled()
+    }
+
+    /// Sets the type tag that's stored in the file's header. The same tag will need to be given
+    /// to open_with_type_tag to reopen the file.
+    pub fn set_type_tag(&mut self, type_tag: u64) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_writable()?;
+        self.type_tag = type_tag;
+        self.write_header_field(TYPE_TAG_OFFSET, type_tag)
+    }
     
   
     /// Makes a deep copy
@@ -82,10 +241,7 @@ impl<T: Desse + DesseSized> VecFile<T> {
         // This could be done much more efficiently, however it's prefered to have shadow
         // protection in case of read/write issues, so we want to do it with VecFile's methods
         
-        let mut clone = Self::default();
-        let shadows: &mut Vec<File> = unsafe { transmute(self.shadows.as_ptr()) };
-
-        clone.add_shadows(shadows.len())?;
+        let mut clone = self.new_empty()?;
         clone.reserve(self.len)?; // Should be relatively safe if shadows are in play
 
         for element in self.into_iter() {
@@ -94,45 +250,274 @@ impl<T: Desse + DesseSized> VecFile<T> {
         Ok(clone)
     }
 
+    /// Creates an empty VecFile in temporary storage, with the same type tag, directories and
+    /// number of shadows as self.
+    fn new_empty(&self) -> Result<Self, Box<dyn std::error::Error>> {
+        let storage = tested_temp(self.temp_dir.as_deref())?;
+        let mut vf = Self::init(storage, 8, self.type_tag, self.copies().checksums.block_size)?;
+
+        vf.temp_dir = self.temp_dir.clone();
+        vf.shadow_dir = self.shadow_dir.clone();
+        vf.read_mode = self.read_mode;
+        vf.copies_mut().stats.observer = self.copies().stats.observer.clone();
+        vf.add_shadows(self.shadow_count())?;
+        Ok(vf)
+    }
+
 
     /// Adds a number of additional shadows to the VecFile.
     /// 
     /// This does not need to be re-done if a shadow is used to replace the original as its done
     /// automatically.
     pub fn add_shadows(&self, additional_shadows: usize) -> Result<(), Box<dyn std::error::Error>> {
-        let shadows: &mut Vec<File> = unsafe { transmute(self.shadows.as_ptr()) };
-        if additional_shadows > 0 {
-            shadows.reserve(additional_shadows);
-            for _ in 0..additional_shadows {
-                let new_shadow = self.new_shadow()?;
-                shadows.push(new_shadow);
+        self.copies_mut().add_shadows(additional_shadows, self.shadow_dir())
+    }
+
+    /// Adds the given storage as a shadow, rather than a temporary one. Whatever the storage
+    /// held is overwritten with a copy of the VecFile.
+    ///
+    /// Note that if this shadow ever replaces the original, or is replaced itself, its
+    /// replacement is temporary storage.
+    pub fn add_shadow_in(&self, storage: H) -> Result<(), Box<dyn std::error::Error>> {
+        self.copies_mut().add_shadow(storage, None, self.shadow_dir())
+    }
+
+    /// Adds a named shadow in a file at the given path, such as on another disk than the
+    /// underlying file. The file is created if it doesn't exist, and whatever it held is
+    /// overwritten with a copy of the VecFile.
+    ///
+    /// Unlike other shadows, a named shadow outlives the VecFile. Its path is kept in the header
+    /// of the underlying file, so reopening the file reopens the shadow too. If the underlying
+    /// file is lost, the shadow can be opened in its place since it's a full copy.
+    ///
+    /// Each time the file is opened its named shadows are brought up to date with a fresh copy
+    /// of it, so opening a file reads the whole of it once for every named shadow it has.
+    ///
+    /// This returns an error if H can't be backed by a file, if there's no room left in the
+    /// header for the path, or with Error::DuplicateShadow if the path is the underlying file or
+    /// already one of its shadows.
+    pub fn add_shadow_at<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_writable()?;
+        let path = absolute_path(path.as_ref())?;
+        let mut copies = self.copies_mut();
+        let shadow_paths = copies.shadows.iter().filter_map(|shadow| shadow.path.as_ref());
+        if copies.primary_path.iter().chain(shadow_paths).any(|named| same_file(named, &path)) {
+            return Err(Error::DuplicateShadow(path).into());
+        }
+        // Make sure the path fits before creating anything
+        copies.manifest(Some(&path))?;
+
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true)
+                       .open(&path)?;
+        copies.add_shadow(H::from_file(file, false)?, Some(path.clone()), self.shadow_dir())?;
+        // It may be a missing shadow that's come back, which is now listed as a shadow instead
+        copies.missing.retain(|missing| *missing != path);
+        copies.write_manifest(self.shadow_dir())
+    }
+
+    /// Returns the paths of the named shadows, see add_shadow_at.
+    pub fn shadow_paths(&self) -> Vec<PathBuf> {
+        self.copies().shadows.iter().filter_map(|shadow| shadow.path.clone()).collect()
+    }
+
+    /// Returns the paths of the named shadows that couldn't be reopened along with the file.
+    /// They're still listed in the header, so they're tried again the next time it's opened,
+    /// until clear_shadows is called.
+    pub fn missing_shadow_paths(&self) -> Vec<PathBuf> {
+        self.copies().missing.clone()
+    }
+
+    /// Reopens the named shadows listed in the header. Each one is brought up to date with a
+    /// fresh copy of the underlying file. A path that's the same file as opened, which is where
+    /// the underlying file was opened from, is dropped from the header since it's no longer a
+    /// shadow.
+    ///
+    /// Any that can't be opened are kept in the header to be tried again next time, and are
+    /// reported to the observer as missing.
+    pub(crate) fn reopen_shadows(&mut self, opened: Option<&Path>)
+        -> Result<(), Box<dyn std::error::Error>> {
+        let mut header = vec![0u8; HEADER_SIZE as usize];
+        self.read_exact_at(0, &mut header)?;
+        let paths = manifest_from_bytes(&header)?;
+
+        let mut copies = self.copies_mut();
+        copies.primary_path = opened.and_then(|opened| std::fs::canonicalize(opened).ok());
+        let mut dropped = false;
+        for path in paths {
+            if copies.primary_path.as_deref().is_some_and(|opened| same_file(opened, &path)) {
+                // This is the underlying file, so it's no longer a shadow
+                dropped = true;
+                continue;
             }
+
+            let shadow = OpenOptions::new().read(true).write(true).open(&path)
+                                           .and_then(|file| H::from_file(file, false));
+            let added = shadow.map_err(|e| e.into())
+                              .and_then(|shadow| {
+                                  copies.add_shadow(shadow, Some(path.clone()), self.shadow_dir())
+                              });
+            if let Err(e) = added {
+                copies.notify(Event::ShadowMissing { path: path.clone(), error: e.to_string() });
+                copies.missing.push(path);
+            }
+        }
+
+        if dropped {
+            copies.write_manifest(self.shadow_dir())?;
         }
         Ok(())
     }
 
-    /// Removes the given number of shadows.
+    /// Removes the given number of shadows, starting with the last one added.
+    ///
+    /// This will panic if a named shadow is removed and the header of the underlying file can't
+    /// be updated.
     pub fn remove_shadows(&mut self, shadow_to_remove: usize) {
-        let shadows: &mut Vec<File> = unsafe { transmute(self.shadows.as_ptr()) };
-        for _ in 0..shadow_to_remove {
-            shadows.pop();
-        }
+        self.copies_mut().remove_shadows(shadow_to_remove, self.shadow_dir()).unwrap();
     }
 
-    /// Removes all shadows
+    /// Removes all shadows, and forgets any named shadows that couldn't be reopened.
+    ///
+    /// This will panic if there are named shadows and the header of the underlying file can't be
+    /// updated.
     pub fn clear_shadows(&mut self) {
-        self.shadows = vec![].into();
+        let mut copies = self.copies_mut();
+        let forgotten = !copies.missing.is_empty();
+        copies.missing.clear();
+        copies.remove_shadows(usize::MAX, self.shadow_dir()).unwrap();
+        if forgotten {
+            copies.write_manifest(self.shadow_dir()).unwrap();
+        }
     }
 
+    /// Returns the number of shadows
+    pub fn shadow_count(&self) -> usize {
+        self.copies().shadows.len()
+    }
 
+    /// Returns the number of shadows that have failed a write or a resize, and been replaced by
+    /// a fresh copy of the file.
+    ///
+    /// A failed shadow is removed even if a replacement can't be made, in which case the shadow
+    /// count drops.
+    pub fn failed_shadow_count(&self) -> u64 {
+        self.copies().stats.failed_shadows
+    }
+
+    /// Returns a snapshot of how the underlying file and its shadows have held up since the
+    /// VecFile was created or opened, see Health.
+    pub fn health(&self) -> Health {
+        self.copies().health()
+    }
 
+    /// Sets a callback that's passed each Event as it happens, such as the underlying file being
+    /// replaced by a shadow. It replaces any callback that was set before, and is shared with
+    /// the VecFiles that are cloned or split off from this one.
+    ///
+    /// The callback is run while the VecFile is locked, so it mustn't use the VecFile itself.
+    /// With the log feature enabled, events are also logged through the log crate.
+    pub fn set_observer<F>(&mut self, observer: F)
+    where F: Fn(&Event) + Send + Sync + 'static {
+        self.copies_mut().stats.observer = Some(Observer(Arc::new(observer)));
+    }
+
+    /// Returns which copies reads are served from, see ReadMode.
+    pub fn read_mode(&self) -> ReadMode {
+        self.read_mode
+    }
+
+    /// Sets which copies reads are served from, see ReadMode.
+    pub fn set_read_mode(&mut self, read_mode: ReadMode) {
+        self.read_mode = read_mode;
+    }
+
+    /// Compares the underlying file and every shadow byte for byte, a block at a time, and
+    /// reports which blocks differ in which copies. Nothing is changed, see repair.
+    ///
+    /// With checksums, the blocks are the checksum blocks and a copy that fails a block's
+    /// checksum is reported as bad too. Otherwise the blocks are 4096 bytes. Only the blocks that
+    /// hold elements are compared, not the header.
+    pub fn scrub(&self) -> ScrubReport {
+        // Copies can't be written to through a shared reference, so this never fails
+        self.scrub_blocks(false).unwrap()
+    }
+
+    /// Scrubs the VecFile like scrub, and overwrites the bad copies of each block with the right
+    /// bytes where it's known what they are. If the underlying file is one of them, it's replaced
+    /// by a shadow instead. The report is of what was found before repairing.
+    ///
+    /// This returns an error if the VecFile is read only, or if the underlying file fails a
+    /// repair and there are no shadows to replace it.
+    pub fn repair(&mut self) -> Result<ScrubReport, Box<dyn std::error::Error>> {
+        self.check_writable()?;
+        self.scrub_blocks(true)
+    }
+
+    fn scrub_blocks(&self, repair: bool) -> Result<ScrubReport, Box<dyn std::error::Error>> {
+        let checksums = self.copies().checksums;
+        let element_size = self.element_size() as u64;
+        let (block_size, data_len) = if checksums.is_enabled() {
+            (checksums.block_size, checksums.data_len)
+        }
+        else {
+            (DEFAULT_CHECKSUM_BLOCK_SIZE, self.cap * element_size)
+        };
+
+        let mut report = ScrubReport {
+            block_size,
+            blocks: (self.len * element_size).div_ceil(block_size),
+            mismatches: Vec::new(),
+        };
+        for block in 0..report.blocks {
+            // Whole blocks are compared, even past the last element, so repairs rewrite whole
+            // checksum blocks
+            let start = block * block_size;
+            let end = std::cmp::min(start + block_size, data_len);
+            let found = self.copies().scrub_block(block, start..end);
+            if let Some((mismatch, right)) = found {
+                if let (true, Some(right)) = (repair, right) {
+                    self.copies_mut().rewrite(&mismatch.bad_copies,
+                                              &right,
+                                              HEADER_SIZE + start,
+                                              self.shadow_dir())?;
+                }
+                report.mismatches.push(mismatch);
+            }
+        }
+        Ok(report)
+    }
 
-    /// Checks that the given index is a useable index, which it will be as long as 
-    /// index * size_of::<T>() + (size_of::<T>() - 1) does not exceed std::u64::MAX
+    /// Locks the storage for reading.
+    ///
+    /// A panic while the lock is held leaves the storage in the same state as an I/O error part
+    /// way through the operation would, so poisoning is ignored.
+    fn copies(&self) -> RwLockReadGuard<'_, Copies<S, H>> {
+        self.copies.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the storage for writing, see copies.
+    pub(crate) fn copies_mut(&self) -> RwLockWriteGuard<'_, Copies<S, H>> {
+        self.copies.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The directory that new shadows are created in
+    fn shadow_dir(&self) -> Option<&Path> {
+        self.shadow_dir.as_deref().or(self.temp_dir.as_deref())
+    }
+
+
+
+
+    /// Checks that the given index is a useable index, which it will be as long as
+    /// HEADER_SIZE + index * element_size() + (element_size() - 1) does not exceed u64::MAX
+    ///
+    /// Every element offset should come from here, since element_size() can differ from
+    /// size_of::<T>() for types with padding.
     fn calc_index(&self, index: u64) -> Result<u64, Error> {
-        
-        // Check that the start index is in range 
+
+        // Check that the start index is in range, elements start after the header
         let start_index = index.checked_mul(self.element_size() as u64)
+                                                .and_then(|offset| offset.checked_add(HEADER_SIZE))
                                                 .ok_or(Error::IndexExceedsMaxU64)?;
         // Check that the end index is in range
         start_index.checked_add(self.element_size() as u64 - 1)
@@ -162,6 +547,26 @@ impl<T: Desse + DesseSized> VecFile<T> {
         self.len
     }
 
+    /// Returns true if the underlying file was opened read only, in which case any method that
+    /// would modify the collection returns an error.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        if self.read_only {
+            Err(Error::ReadOnly)
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    /// Returns true if there are no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
    
     /// Tries to return the element at the given index.
     ///
@@ -173,13 +578,7 @@ impl<T: Desse + DesseSized> VecFile<T> {
             return Err(Error::OutOfRange(index, self.len).into());
         }
 
-        let offset_index = self.calc_index(index)?;
-        let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
-         
-        file.seek(SeekFrom::Start(offset_index))?;
-        let ret = self.read_at_curr_seek()?;
-        self.reset_seek_to_len()?;
-        Ok(ret)
+        self.read_at(index)
     }
 
     /// Returns the element at the given index.
@@ -189,24 +588,108 @@ impl<T: Desse + DesseSized> VecFile<T> {
         self.try_get(index).unwrap()
     }
 
+    /// Returns an iterator over the elements.
+    ///
+    /// The iterator will panic if the underlying file is no longer accessible and there's no
+    /// shadows to replace it, see try_iter.
+    pub fn iter(&self) -> VecFileIterator<'_, T, S, H> {
+        VecFileIterator::new(self)
+    }
+
+    /// Returns an iterator over the elements that yields an error, rather than panicking, if the
+    /// underlying file is no longer accessible.
+    pub fn try_iter(&self) -> VecFileTryIterator<'_, T, S, H> {
+        VecFileTryIterator { iter: self.iter() }
+    }
+
+    /// Returns an iterator over the elements in the given range.
+    ///
+    /// This will panic if the range is out of bounds, like slicing a Vec would.
+    pub fn iter_range<R: RangeBounds<u64>>(&self, range: R) -> VecFileIterator<'_, T, S, H> {
+        let (start, end) = self.range_to_indices(range).unwrap();
+        VecFileIterator::with_range(self, start, end)
+    }
+
+    /// Tries to read the elements in the given range into a Vec, with a single read.
+    ///
+    /// This will return Err if the range is out of bounds, or if the underlying file is no longer
+    /// accessible.
+    pub fn try_read_range<R: RangeBounds<u64>>(&self, range: R)
+        -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        let (start, end) = self.range_to_indices(range)?;
+        let count = usize::try_from(end - start).map_err(|_| Error::LenExceedsUsize(end - start))?;
+        self.read_many(start, count)
+    }
+
+    /// Reads the elements in the given range into a Vec, with a single read.
+    ///
+    /// This will panic if the range is out of bounds, or if the underlying file is no longer
+    /// accessible.
+    pub fn read_range<R: RangeBounds<u64>>(&self, range: R) -> Vec<T> {
+        self.try_read_range(range).unwrap()
+    }
+
+    /// Tries to fill dest with the elements starting at start, with a single read.
+    ///
+    /// This will return Err if there aren't dest.len() elements from start, or if the underlying
+    /// file is no longer accessible, in which case dest may have been partially written.
+    pub fn try_read_into(&self, start: u64, dest: &mut [T])
+        -> Result<(), Box<dyn std::error::Error>> {
+        let end = start.checked_add(dest.len() as u64).ok_or(Error::IndexExceedsMaxU64)?;
+        self.range_to_indices(start..end)?;
+
+        let buf = self.read_bytes(start, dest.len())?;
+        for (element, bytes) in dest.iter_mut().zip(buf.chunks_exact(self.element_size())) {
+            *element = de_from(bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Fills dest with the elements starting at start, with a single read.
+    ///
+    /// This will panic if there aren't dest.len() elements from start, or if the underlying file
+    /// is no longer accessible.
+    pub fn read_into(&self, start: u64, dest: &mut [T]) {
+        self.try_read_into(start, dest).unwrap()
+    }
+
+    /// Turns range into a start index and an end index one past the last element, checking that
+    /// it's within the current len.
+    fn range_to_indices<R: RangeBounds<u64>>(&self, range: R) -> Result<(u64, u64), Error> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.checked_add(1).ok_or(Error::IndexExceedsMaxU64)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.checked_add(1).ok_or(Error::IndexExceedsMaxU64)?,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len,
+        };
+
+        if start > end {
+            Err(Error::InvalidRange(start, end))
+        }
+        else if end > self.len {
+            Err(Error::OutOfRange(end, self.len))
+        }
+        else {
+            Ok((start, end))
+        }
+    }
+
     /// Tries to set the element at the given index to value.
     ///
     /// This will return Err if index is out of range, or if the underlying file is no longer
     /// accessible.
     pub fn try_set(&mut self, index: u64, value: &T) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_writable()?;
         if !self.bounds_check(index) {
             // Index is out of range
             return Err(Error::OutOfRange(index, self.len).into());
-        } 
-        let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
-
-
+        }
 
-        let offset_index = self.calc_index(index)?;
-        file.seek(SeekFrom::Start(offset_index))?;
-        self.write_at_curr_seek(value)?;
-        self.reset_seek_to_len()?;
-        Ok(())
+        self.write_at(index, value)
     }
 
     /// Sets the element at the given index to value.
@@ -216,9 +699,63 @@ impl<T: Desse + DesseSized> VecFile<T> {
         self.try_set(index, value).unwrap()
     }
 
+    /// Tries to overwrite the elements starting at index with the elements of slice, with a
+    /// single write to the underlying file and each of its shadows.
+    ///
+    /// This will return Err if any of the elements would be out of range, in which case nothing
+    /// is written, or if the underlying file is no longer accessible.
+    pub fn try_write_slice_at(&mut self, index: u64, slice: &[T])
+        -> Result<(), Box<dyn std::error::Error>> {
+        self.try_set_from_iter(index, slice)
+    }
+
+    /// Overwrites the elements starting at index with the elements of slice, with a single write
+    /// to the underlying file and each of its shadows.
+    ///
+    /// This will panic if any of the elements would be out of range, or if the underlying file is
+    /// no longer accessible.
+    pub fn write_slice_at(&mut self, index: u64, slice: &[T]) {
+        self.try_write_slice_at(index, slice).unwrap()
+    }
+
+    /// Tries to overwrite the elements starting at index with the elements of iter. They're all
+    /// serialized first and then written with a single write to the underlying file and each of
+    /// its shadows.
+    ///
+    /// This will return Err if any of the elements would be out of range, in which case nothing
+    /// is written, or if the underlying file is no longer accessible.
+    pub fn try_set_from_iter<'b, U>(&mut self, index: u64, iter: U)
+        -> Result<(), Box<dyn std::error::Error>>
+    where U: IntoIterator<Item=&'b T>,
+          T: 'b {
+        self.check_writable()?;
+        let bytes = ser_many(iter)?;
+        let count = (bytes.len() / self.element_size()) as u64;
+        match index.checked_add(count) {
+            Some(end) if end <= self.len => (),
+            // Either the first element or the last one is out of range
+            _ => return Err(Error::OutOfRange(std::cmp::max(index, self.len), self.len).into()),
+        }
+
+        self.write_all_at(self.calc_index(index)?, &bytes)
+    }
+
+    /// Overwrites the elements starting at index with the elements of iter. They're all
+    /// serialized first and then written with a single write to the underlying file and each of
+    /// its shadows.
+    ///
+    /// This will panic if any of the elements would be out of range, or if the underlying file is
+    /// no longer accessible.
+    pub fn set_from_iter<'b, U>(&mut self, index: u64, iter: U)
+    where U: IntoIterator<Item=&'b T>,
+          T: 'b {
+        self.try_set_from_iter(index, iter).unwrap()
+    }
+
     /// Resizes the len to fit the new_len. If new_len is less than the current len, the elements
     /// are just truncated. The given value is inserted into every added index.
     pub fn resize(&mut self, new_len: u64, value: &T) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_writable()?;
         if new_len > self.len {
             while self.cap < new_len {
                 self.expand()?;
@@ -226,13 +763,13 @@ impl<T: Desse + DesseSized> VecFile<T> {
             while self.len() < new_len {
                 // We could just continually call push here, but we know we don't need to do 
                 // expansion checks or bound checks, so this will be faster
-                self.write_at_curr_seek(value)?;
-                self.len = self.len + 1;
+                self.write_at(self.len, value)?;
+                self.len += 1;
             }
 
         }
         self.len = new_len;
-        Ok(())
+        self.write_len()
 
     }
 
@@ -241,6 +778,7 @@ impl<T: Desse + DesseSized> VecFile<T> {
     pub fn resize_with<F>(&mut self, new_len: u64, mut f: F) 
         -> Result<(), Box<dyn std::error::Error>> 
         where F: FnMut() -> T   {
+        self.check_writable()?;
 
         if new_len > self.len {
             while self.cap < new_len {
@@ -249,39 +787,41 @@ impl<T: Desse + DesseSized> VecFile<T> {
             while self.len() < new_len {
                 // We could just continually call push here, but we know we don't need to do 
                 // expansion checks or bound checks, so this will be faster
-                self.write_at_curr_seek(&(f()))?;
-                self.len = self.len + 1;
+                self.write_at(self.len, &(f()))?;
+                self.len += 1;
             }
 
         }
         self.len = new_len;
-        Ok(())
+        self.write_len()
 
     }
 
     /// Reserves capacity for at least 'additional' more elements
     pub fn reserve(&mut self, additional: u64) -> Result<(), Box<dyn std::error::Error>> {
-        let needed_cap = self.len + additional;
+        self.check_writable()?;
+        let needed_cap = self.len.checked_add(additional).ok_or(Error::IndexExceedsMaxU64)?;
         while self.cap < needed_cap {
             self.expand()?;
         }
         Ok(())
     }
 
-    /// Tries to copy all elements from slice to the collection
+    /// Tries to copy all elements from slice to the collection, with a single write to the
+    /// underlying file and each of its shadows.
     pub fn try_extend_from_slice(&mut self, slice: &[T]) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_writable()?;
         // Check that the last index doesn't exceed u64
-        self.calc_index(self.len + slice.len() as u64)?; 
-        
+        let new_len = self.len.checked_add(slice.len() as u64).ok_or(Error::IndexExceedsMaxU64)?;
+        self.calc_index(new_len)?;
+
         self.reserve(slice.len() as u64)?;  // Reserve the addtional space
-        self.len = self.len + slice.len() as u64; // Add the slice's len to the collections
 
         // Copy in the slice
-        for e in slice {
-            self.write_at_curr_seek(&e).unwrap();
-        }
-        Ok(())
-        
+        self.write_all_at(self.calc_index(self.len)?, &ser_many(slice)?)?;
+        self.len = new_len;
+        self.write_len()
+
     }
 
     /// Tries to copy all elements from slice to the collection
@@ -291,19 +831,32 @@ impl<T: Desse + DesseSized> VecFile<T> {
 
 
 
-    /// Truncates the collection to new_len, or does nothing if new_len is greater than the
+    /// Tries to truncate the collection to new_len, or does nothing if new_len is greater than the
     /// current length
-    pub fn truncate(&mut self, new_len: u64) {
+    ///
+    /// This will return an error if the new len can't be written to the underlying file's header.
+    pub fn try_truncate(&mut self, new_len: u64) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_writable()?;
         if self.len > new_len {
             self.len = new_len;
+            self.write_len()?;
         }
+        Ok(())
+    }
+
+    /// Truncates the collection to new_len, or does nothing if new_len is greater than the
+    /// current length
+    ///
+    /// This will panic if the new len can't be written to the underlying file's header.
+    pub fn truncate(&mut self, new_len: u64) {
+        self.try_truncate(new_len).unwrap()
     }
 
     /// Tries to create a VecFile from an iterator
     pub fn try_from_iter<U: IntoIterator<Item=T>>(&mut self, iter: U) 
         -> Result<Self, Box<dyn std::error::Error>> {
 
-        let mut vf = VecFile::new();
+        let mut vf = Self::default();
         vf.add_shadows(1)?; // To protect against potential read errors.
 
         for element in iter {
@@ -315,26 +868,14 @@ impl<T: Desse + DesseSized> VecFile<T> {
     }
 
     fn expand(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
-        let shadows: &mut Vec<File> = unsafe { transmute(self.shadows.as_ptr()) };
-        self.cap = self.cap * 2;
-        let new_file_size = self.cap * (self.element_size() as u64);
-        while let Err(_) = file.set_len(new_file_size) {
-            self.replace_with_shadow()?;
-        }
-        for i in 0..shadows.len() {
-            match shadows[i].set_len(new_file_size) {
-                Ok(_) => (),
-                Err(_) => {
-                    // This shadow is having write issues, replace it with another shadow.
-                    // This new replacement doesn't need to be expanded like the others since it's
-                    // a fresh copy of the original which has already been expanded.
-                    self.replace_shadow_at_index(i)?;
-                }
-            }
-
-        }
-        Ok(())
+        // A collection can be created with no capacity, so make sure it actually grows
+        let cap = std::cmp::max(self.cap.checked_mul(2).ok_or(Error::IndexExceedsMaxU64)?, 1);
+        let new_size = cap.checked_mul(self.element_size() as u64)
+                          .and_then(|size| size.checked_add(HEADER_SIZE))
+                          .ok_or(Error::IndexExceedsMaxU64)?;
+        self.copies_mut().set_len(new_size, self.shadow_dir())?;
+        self.cap = cap;
+        self.write_header_field(CAP_OFFSET, cap)
     }
 
     fn expand_if_needed(&mut self) -> Result<(), Box<dyn std::error::Error>> {
@@ -344,10 +885,14 @@ impl<T: Desse + DesseSized> VecFile<T> {
         Ok(())
     }
 
-    fn reset_seek_to_len(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
-        file.seek(SeekFrom::Start(self.calc_index(self.len)?))?;
-        Ok(())
+    /// Writes the current len to the header of the file and its shadows.
+    fn write_len(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_header_field(LEN_OFFSET, self.len)
+    }
+
+    /// Overwrites the u64 header field at offset in the file and all of its shadows.
+    fn write_header_field(&self, offset: u64, value: u64) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_all_at(offset, &value.to_le_bytes())
     }
 
 
@@ -356,11 +901,12 @@ impl<T: Desse + DesseSized> VecFile<T> {
     /// This will return an Error if the underlying file's len would exceed std::u64::MAX or 
     /// if the underlying file has write issues and no shadows exist.
     pub fn try_push(&mut self, value: &T) -> Result<(), Box<dyn std::error::Error>> {
-        if let Ok(_) = self.calc_index(self.len) {
+        self.check_writable()?;
+        if self.calc_index(self.len).is_ok() {
             self.expand_if_needed()?;
-            self.write_at_curr_seek(value)?;
-            self.len = self.len + 1;
-            Ok(())
+            self.write_at(self.len, value)?;
+            self.len += 1;
+            self.write_len()
         }
         else {
             Err(Error::PushOnFull.into())
@@ -380,18 +926,13 @@ impl<T: Desse + DesseSized> VecFile<T> {
     /// This will return an error if the underlying file has read issues and no shadows exist or
     /// if the list is empty.
     pub fn try_pop(&mut self) -> Result<T, Box<dyn std::error::Error>> {
+        self.check_writable()?;
         if self.len > 0 {
-            let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
-            // The collection is not empty 
-            // Moves back to the last element
-            file.seek(SeekFrom::Current(-(self.element_size() as i64)))?; 
-
-            let ret = self.read_at_curr_seek()?;
-            // The 'cursor' is now where it began, but we just popped the last element, move back
-            // one element to point to the end of the collection.
-            file.seek(SeekFrom::Current(-(self.element_size() as i64)))?; 
-            
-            self.len = self.len - 1; // Decrement len
+            // The collection is not empty, read the last element
+            let ret = self.read_at(self.len - 1)?;
+
+            self.len -= 1; // Decrement len
+            self.write_len()?;
             Ok(ret)
         }
         else {
@@ -414,54 +955,62 @@ impl<T: Desse + DesseSized> VecFile<T> {
         std::mem::size_of::<<T as Desse>::Output>()
     }
 
+    /// Returns the max number of elements that can fit in a file alongside its header.
     pub fn max_capacity() -> u64 {
-        std::u64::MAX / std::mem::size_of::<<T as Desse>::Output>() as u64  
+        (u64::MAX - HEADER_SIZE) / std::mem::size_of::<<T as Desse>::Output>() as u64
     }
 
 
-    /// Copies the original underlying file into a new file at path.
+    /// Copies the original underlying file into a new file at path, which then replaces it.
     /// If a file exists there, it gets truncated.
+    ///
+    /// Named shadows move over to the new file, so they're dropped from the header of the
+    /// original and only reopened along with the new file.
+    ///
+    /// This returns an error if S can't be backed by a file, such as `Vec<u8>`, though the copy at
+    /// path is still made.
     pub fn to_named_file<U: AsRef<std::path::Path>>(&mut self, path: U) 
         -> Result<(), Box<dyn std::error::Error>> {
 
-        let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
         let mut named_file = std::fs::OpenOptions::new()
                                 .read(true)
                                 .write(true)
                                 .create(true)
                                 .truncate(true)
-                                .open(path)?;
-
-        std::io::copy(file, &mut named_file)?;
-        std::mem::drop(file); // Drop before it becomes a dangling pointer.
-        self.file = named_file.into();
+                                .open(&path)?;
+
+        {
+            let mut copies = self.copies_mut();
+            // Copy from the very beginning so the header comes along with the elements
+            copies.copy_primary_to(&mut named_file)?;
+            let named_file = S::from_file(named_file, false)?;
+            let named = copies.shadows.iter().any(|shadow| shadow.path.is_some());
+            if named || !copies.missing.is_empty() {
+                // Otherwise reopening the original would overwrite the shadows with its elements
+                let checksums = copies.checksums;
+                let manifest = manifest_to_bytes(std::iter::empty())?;
+                copies.primary.write_checked(&checksums, &manifest, MANIFEST_OFFSET)?;
+            }
+            copies.primary = Primary::Original(named_file);
+            copies.primary_path = std::fs::canonicalize(&path).ok();
+        }
+        self.read_only = false; // The copy is always writable
         Ok(())
     }
 
+    /// Flushes the underlying storage and all of its shadows, so that everything that's been
+    /// written has reached wherever they're persisted.
+    pub fn sync(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.copies_mut().sync(self.shadow_dir())
+    }
+
     /// Tries to insert the given element into te specified index and shifts the elements to the 
     /// right.
     ///
     /// This will return an error if index > self.len or if there's an issue with the underlying
     /// file.
     pub fn try_insert(&mut self, index: u64, element: &T) -> Result<(), Box<dyn std::error::Error>> {
-        if !self.bounds_check(index) {
-            return Err(Error::OutOfRange(index, self.len).into());
-        }
-
-        self.expand_if_needed()?; // Expand if the collection is currently full
-
-        self.len = self.len + 1;
-        // Increment the len for the shuffle, this also means we don't need to iterate to the len +
-        // 1.
-
-        // Shuffle all elements to the right
-        for i in (index..self.len).rev() {
-            let curr = self.try_get(i - 1)?;
-            self.try_set(i, &curr)?;
-        }
-        
-        self.set(index, element); // Insert the new element
-        Ok(())
+        self.try_insert_slice(index, std::slice::from_ref(element))
     }
 
     /// Insert the given element into te specified index and shifts the elements to the 
@@ -473,24 +1022,51 @@ impl<T: Desse + DesseSized> VecFile<T> {
         self.try_insert(index, element).unwrap();
     }
 
+    /// Tries to insert the elements of slice starting at the specified index, shifting the
+    /// elements after it to the right. The elements are shifted a block at a time rather than one
+    /// by one.
+    ///
+    /// This will return an error if index > self.len or if there's an issue with the underlying
+    /// file.
+    pub fn try_insert_slice(&mut self, index: u64, slice: &[T])
+        -> Result<(), Box<dyn std::error::Error>> {
+        self.check_writable()?;
+        if index > self.len {
+            return Err(Error::OutOfRange(index, self.len).into());
+        }
+        // Check that the last index doesn't exceed u64
+        let new_len = self.len.checked_add(slice.len() as u64).ok_or(Error::IndexExceedsMaxU64)?;
+        self.calc_index(new_len)?;
+
+        self.reserve(slice.len() as u64)?;
+
+        // Shift all elements after index to the right, then write the slice into the gap
+        self.copy_within(index, index + slice.len() as u64, self.len - index)?;
+        self.write_all_at(self.calc_index(index)?, &ser_many(slice)?)?;
+        self.len = new_len;
+        self.write_len()
+    }
+
+    /// Inserts the elements of slice starting at the specified index, shifting the elements after
+    /// it to the right.
+    ///
+    /// This will panic if index > self.len or if there's an issue with the underlying file.
+    pub fn insert_slice(&mut self, index: u64, slice: &[T]) {
+        self.try_insert_slice(index, slice).unwrap();
+    }
+
 
     /// Tries to remove the element at the specified index and shfits the elements to the left.
     ///
     /// This will return an error if index > self.len or if there's an issue with the underlying
     /// file.
     pub fn try_remove(&mut self, index: u64) -> Result<T, Box<dyn std::error::Error>> {
+        self.check_writable()?;
         if !self.bounds_check(index) {
             return Err(Error::OutOfRange(index, self.len).into());
         }
 
-        let ret_element = self.try_get(index)?;
-
-        for i in index..(self.len - 1) {
-            let curr = self.try_get(i + 1)?;
-            self.try_set(i, &curr)?;
-        }
-        self.len = self.len - 1;
-        Ok(ret_element)
+        Ok(self.try_remove_range(index..=index)?.remove(0))
     }
 
 
@@ -502,151 +1078,213 @@ impl<T: Desse + DesseSized> VecFile<T> {
         self.try_remove(index).unwrap()
     }
 
+    /// Tries to remove the elements in the given range and return them, shifting the elements
+    /// after it to the left. The elements are shifted a block at a time rather than one by one.
+    ///
+    /// This will return an error if the range is out of bounds or if there's an issue with the
+    /// underlying file.
+    pub fn try_remove_range<R: RangeBounds<u64>>(&mut self, range: R)
+        -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        self.check_writable()?;
+        let (start, end) = self.range_to_indices(range)?;
+        let removed = self.try_read_range(start..end)?;
+
+        self.copy_within(end, start, self.len - end)?;
+        self.len -= end - start;
+        self.write_len()?;
+        Ok(removed)
+    }
+
+    /// Removes the elements in the given range and returns them, shifting the elements after it
+    /// to the left.
+    ///
+    /// This will panic if the range is out of bounds or if there's an issue with the underlying
+    /// file.
+    pub fn remove_range<R: RangeBounds<u64>>(&mut self, range: R) -> Vec<T> {
+        self.try_remove_range(range).unwrap()
+    }
 
+    /// Tries to split the collection in two at the given index. The elements from at onward are
+    /// moved into a new VecFile in temporary storage, which has as many shadows as self.
+    ///
+    /// This will return an error if at > self.len or if there's an issue with either file.
+    pub fn try_split_off(&mut self, at: u64) -> Result<Self, Box<dyn std::error::Error>> {
+        self.check_writable()?;
+        if at > self.len {
+            return Err(Error::OutOfRange(at, self.len).into());
+        }
 
-
-    fn read_at_curr_seek(&self) -> Result<T, Box<dyn std::error::Error>> {
-        let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
-        let element_size = self.element_size();
-        let mut buf = Vec::with_capacity(element_size);
-        while let Err(_) =  file.take(element_size as u64).read_to_end(&mut buf) {
-            // A read error occured for some reason, replace the main file with one of it's
-            // shadows
-            self.replace_with_shadow()?;
+        let mut other = self.new_empty()?;
+        other.reserve(self.len - at)?;
+        let block_len = std::cmp::max(1, COPY_BUF_SIZE / self.element_size()) as u64;
+        let mut start = at;
+        while start < self.len {
+            let end = std::cmp::min(start.saturating_add(block_len), self.len);
+            other.try_extend_from_slice(&self.try_read_range(start..end)?)?;
+            start = end;
         }
-            
 
-        // We know the size of <T as Desse>::Output, and we know it's a u8 array of that
-        // size, so even though the compilier doesn't know that, we can use transmute to treat it
-        // as such. This should always be safe as long as <T as Desse>::Output is a array of u8.
-        Ok(de_from::<T>(&buf)?)
+        self.try_truncate(at)?;
+        Ok(other)
     }
 
+    /// Splits the collection in two at the given index. The elements from at onward are moved
+    /// into a new VecFile in temporary storage, which has as many shadows as self.
+    ///
+    /// This will panic if at > self.len or if there's an issue with either file.
+    pub fn split_off(&mut self, at: u64) -> Self {
+        self.try_split_off(at).unwrap()
+    }
 
-    fn write_at_curr_seek(&mut self, value: &T) -> Result<(), Box<dyn std::error::Error>> {
-        let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
-        let shadows: &mut Vec<File> = unsafe { transmute(self.shadows.as_ptr()) };
-        let value_ser = ser_to::<T>(value)?;
 
-        while let Err(_) = file.write_all(value_ser.as_slice()) {
-            // The write failed for some reason, replace the main file with one of it's shadows
-            self.replace_with_shadow()?;
-        }
-        for shadow in shadows {
-            //TODO if a write fails replace it with a new shadow
-            shadow.write_all(value_ser.as_slice())?;
-        }
-        Ok(())
+
+
+    /// Reads the element at the given index, no bounds checks are done.
+    fn read_at(&self, index: u64) -> Result<T, Box<dyn std::error::Error>> {
+        let mut buf = vec![0u8; self.element_size()];
+        self.read_exact_at(self.calc_index(index)?, &mut buf)?;
+
+        // The bytes are the element as serialized by Desse, which de_from deserializes back into
+        // a T after checking that there's the right number of them.
+        de_from::<T>(&buf)
     }
 
-    fn replace_with_shadow(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let shadows: &mut Vec<File> = unsafe { transmute(self.shadows.as_ptr()) };
-        if shadows.len() == 0 {
-            // This should never happen, but if it does, that means no shadows exist to replace the
-            // original file, and this would only be called if the original file is no longer
-            // accessible. In such a case, we are in a very bad state, so panic.
-            panic!("This is a bug. Shadow replacement shouldn't occur when no shadows have been set");
-        }
+    /// Reads count elements starting at index with a single read, no bounds checks are done.
+    pub(crate) fn read_many(&self, index: u64, count: usize)
+        -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        let buf = self.read_bytes(index, count)?;
+        buf.chunks_exact(self.element_size()).map(de_from).collect()
+    }
+
+    /// Reads the serialized bytes of count elements starting at index, no bounds checks are done.
+    fn read_bytes(&self, index: u64, count: usize) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut buf = vec![0u8; count.checked_mul(self.element_size())
+                                     .ok_or(Error::IndexExceedsMaxU64)?];
+        self.read_exact_at(self.calc_index(index)?, &mut buf)?;
+        Ok(buf)
+    }
 
-        self.file.replace(shadows.pop().unwrap());
-        self.add_shadows(1)?;
+    /// Copies count elements starting at src so that they start at dest instead, a block at a
+    /// time. The copy is made in the storage and all of its shadows, and no bounds checks are done.
+    fn copy_within(&self, src: u64, dest: u64, count: u64)
+        -> Result<(), Box<dyn std::error::Error>> {
+        let element_size = self.element_size() as u64;
+        let block_len = std::cmp::max(1, COPY_BUF_SIZE as u64 / element_size);
+        let mut buf = vec![0u8; (std::cmp::min(block_len, count) * element_size) as usize];
+
+        let mut copied = 0;
+        while copied < count {
+            let len = std::cmp::min(block_len, count - copied);
+            // When moving to the right, copy from the end so nothing is overwritten before it's
+            // been copied, and vice versa.
+            let offset = if dest > src { count - copied - len } else { copied };
+            let buf = &mut buf[..(len * element_size) as usize];
+            self.read_exact_at(self.calc_index(src + offset)?, buf)?;
+            self.write_all_at(self.calc_index(dest + offset)?, buf)?;
+            copied += len;
+        }
         Ok(())
+    }
 
+    /// Writes value to the given index, no bounds checks are done.
+    fn write_at(&mut self, index: u64, value: &T) -> Result<(), Box<dyn std::error::Error>> {
+        let value_ser = ser_to::<T>(value)?;
+        self.write_all_at(self.calc_index(index)?, value_ser.as_slice())
     }
 
-    fn replace_shadow_at_index(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
-        let shadows: &mut Vec<File> = unsafe { transmute(self.shadows.as_ptr()) };
-        let replacement = self.new_shadow()?;
-        shadows[index] = replacement;
-        Ok(())
+    /// Fills buf with the bytes starting at offset in the storage, replacing it with a shadow if
+    /// the read fails. With ReadMode::Majority, the bytes are voted on instead.
+    fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if self.read_mode == ReadMode::Majority {
+            if let Some(result) = self.read_majority(offset, buf) {
+                return result;
+            }
+        }
+
+        // Reads only need the primary, so they share the lock unless it has to be replaced
+        if self.copies().read_primary(buf, offset).is_ok() {
+            return Ok(());
+        }
+        self.copies_mut().read_exact_at(buf, offset, self.shadow_dir())
     }
 
-    /// Creates a new shadow of the VecFile's file
-    fn new_shadow(&self) -> Result<File, Box<dyn std::error::Error>> {
-        let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
-        let shadows: &mut Vec<File> = unsafe { transmute(self.shadows.as_ptr()) };
-        // Continually generate temporary files until one passes the read/write test
-        let mut shadow = tested_tempfile();
-
-        shadow.set_len(self.cap * self.element_size() as u64)?;
-        let mut orig_read_fail_counter = 0;
-        let orig_read_fail_counter_max = 5;
-
-        while let Err(_) = file.seek(SeekFrom::Start(0))
-                                .and(std::io::copy(file, &mut shadow)) {
-            // Either the seek or the copy failed, so test the dest file for writeability.        
-           
-            if let Ok(_) = rw_test(&mut shadow) {
-                // The destination file is passing read/write tests still, so the issue
-                // lies with the original file potentially.
-                
-                orig_read_fail_counter = orig_read_fail_counter + 1;
-                if orig_read_fail_counter == orig_read_fail_counter_max {
-                    // The original file has failed too many times.
-                    if shadows.len() == 0 {
-                        // The destination file is ok, so there's an issue with the
-                        // original, and with no other shadows, the data is irrecoverable.
-                        return Err(Error::IrrecoverableState.into());
-                    }
-                    else {
-                        // Replace the original 
-                        self.replace_with_shadow()?;
-                    }
-                }
-                // Seek file back to the beginning. Unwrawpping is safe since we know it passed the
-                // rw test
-                shadow.seek(SeekFrom::Start(0)).unwrap();
+    /// Reads the bytes starting at offset from every copy, filling buf with whichever bytes
+    /// more than half of the readable copies hold. Unless the VecFile is read only, any shadows
+    /// that hold other bytes are then overwritten with them, and the primary is replaced by a
+    /// shadow if it's one of the copies that were wrong or unreadable.
+    ///
+    /// Returns None if there's no shadows to vote with.
+    fn read_majority(&self, offset: u64, buf: &mut [u8])
+        -> Option<Result<(), Box<dyn std::error::Error>>> {
+        let (right, bad_copies) = {
+            let copies = self.copies();
+            if copies.shadows.is_empty() {
+                return None;
             }
+            vote(copies.read_copies(buf.len(), offset))
+        };
+
+        let right = match right {
+            Some(right) => right,
+            None => return Some(Err(Error::NoMajority.into())),
+        };
+        buf.copy_from_slice(&right);
+        if bad_copies.is_empty() || self.read_only {
+            return Some(Ok(()));
+        }
 
-            else {
-                // Something happened to the tested temp file between generation and 
-                // copying data over. Generate a new one.
-                shadow = tested_tempfile();
-                orig_read_fail_counter = 0;
+        // Vote again now that nothing else can change the copies, so the right ones are rewritten
+        let mut copies = self.copies_mut();
+        match vote(copies.read_copies(buf.len(), offset)) {
+            (Some(right), bad_copies) => {
+                Some(copies.rewrite(&bad_copies, &right, offset, self.shadow_dir()))
             }
+            (None, _) => Some(Ok(())),
         }
-        self.reset_seek_to_len()?;
-        shadow.seek(SeekFrom::Start(self.calc_index(self.len()).unwrap())).unwrap();
-        Ok(shadow)
     }
-        
 
+    /// Writes all of bytes at offset in the storage and all of its shadows. If the storage fails
+    /// it's replaced with a shadow.
+    fn write_all_at(&self, offset: u64, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.copies_mut().write_all_at(bytes, offset, self.shadow_dir())
+    }
 }
 
 
 /// Implements PartialEq for any type whose reference implements IntoIterator<Item=&T>
 /// Unfortunately, because of orphaning rules, this cannot be implemented as U == VecFile
 /// as well, so VecFile will always have to come first in equalities. 
-impl<T, U> PartialEq<U> for VecFile<T> 
+impl<T, S, H, U> PartialEq<U> for VecFile<T, S, H> 
 where T: Desse + DesseSized + PartialEq + Eq,
+      S: Storage,
+      H: Storage,
       for<'a> &'a U: IntoIterator<Item=&'a T> {
 
     fn eq(&self, other: &U) -> bool {
-        self.into_iter().zip(other.into_iter()).all(|(e1, e2)| &e1 == e2)
+        let mut other = other.into_iter();
+        self.into_iter().all(|e1| other.next() == Some(&e1)) && other.next().is_none()
     }
 }
-impl<T> PartialEq for VecFile<T> 
-where T: Desse + DesseSized + std::fmt::Debug + PartialEq + Eq {
+impl<T, S, H> PartialEq for VecFile<T, S, H> 
+where T: Desse + DesseSized + PartialEq + Eq,
+      S: Storage,
+      H: Storage {
     fn eq(&self, other: &Self) -> bool {
-        self.into_iter().zip(other.into_iter()).all(|(e1, e2)| dbg!(e1) == dbg!(e2))
+        self.len() == other.len() && self.into_iter().eq(other)
     }
 }
 
 
-impl<T: Desse + DesseSized> Default for VecFile<T> {
+impl<T: Desse + DesseSized, S: Storage, H: Storage> Default for VecFile<T, S, H> {
     fn default() -> Self {
-        Self {
-            file: tested_tempfile().into(),
-            shadows: Vec::with_capacity(0).into(), // Wait to allocate, since most won't use shadows
-            len: 0,
-            cap: 8,
-            _phantom: PhantomData,
-        }
+        // The storage already passed a read/write test, so writing the header should succeed
+        let storage = tested_temp(None).unwrap();
+        Self::init(storage, 8, Self::default_type_tag(), 0).unwrap()
     }
 }
 
 
-impl<T: Desse + DesseSized> Clone for VecFile<T> {
+impl<T: Desse + DesseSized, S: Storage, H: Storage> Clone for VecFile<T, S, H> {
     fn clone(&self) -> Self {
         self.try_clone().unwrap()
     }
@@ -654,101 +1292,72 @@ impl<T: Desse + DesseSized> Clone for VecFile<T> {
 
 
 
-impl<T: Desse + DesseSized + PartialEq + Eq + std::fmt::Debug> VecFile<T> { 
+impl<T, S, H> VecFile<T, S, H>
+where T: Desse + DesseSized + PartialEq + Eq + std::fmt::Debug,
+      S: Storage,
+      H: Storage { 
+    /// Checks that every shadow holds the same elements as the original. See scrub for a check
+    /// that doesn't need to deserialize the elements and reports where the copies differ.
     pub fn confirm_shadow_equivalence(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
-        
-        let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
-        let shadows: &mut Vec<File> = unsafe { transmute(self.shadows.as_ptr()) };
-
-        if shadows.len() > 1 {
-            // Compare the first two shadows
-            for i in 0..shadows.len() - 1 {
-                unsafe {
-                    let s1 = VecFile::<T>::from_raw_parts(shadows[i].try_clone().unwrap(),
-                                                         self.len,
-                                                         self.cap);
-                    let s2 = VecFile::<T>::from_raw_parts(shadows[i + 1].try_clone().unwrap(),
-                                                         self.len,
-                                                         self.cap);
-
-                    // Iterate through both the current shadow and next shadow and check that all
-                    // elements are equal
-                    if !s1.into_iter()
-                             .zip(s2.into_iter())
-                             .all(|(e1, e2)| e1 == e2) {
-                        return Ok(false);
-                    }
+        let copies = self.copies();
+        let mut orig_buf = vec![0u8; self.element_size()];
+        let mut shadow_buf = vec![0u8; self.element_size()];
+
+        for i in 0..self.len {
+            let offset = self.calc_index(i)?;
+            copies.primary.read_exact_at(&mut orig_buf, offset)?;
+            let orig = de_from::<T>(&orig_buf)?;
+
+            for shadow in copies.shadows.iter() {
+                shadow.storage.read_exact_at(&mut shadow_buf, offset)?;
+                if de_from::<T>(&shadow_buf)? != orig {
+                    return Ok(false);
                 }
             }
         }
-
-        // All shadows are equivalent, or there's only one shadow.
-        // Compare the first shadow to the main original
-        unsafe {
-            let orig = VecFile::<T>::from_raw_parts(file.try_clone().unwrap(),
-                                                   self.len,
-                                                   self.cap);
-            let s1 = VecFile::from_raw_parts(shadows[0].try_clone().unwrap(),
-                                                self.len,
-                                                self.cap);
-
-
-            let ret = orig.into_iter()
-                            .zip(s1.into_iter())
-                            .all(|(e1, e2)| e1 == e2);
-            self.reset_seek_to_len()?;
-            Ok(ret)
-        }
+        Ok(true)
     }
 } 
 
 
         
 
-impl<T: Desse + DesseSized> std::iter::IntoIterator for &VecFile<T> {
+impl<'a, T: Desse + DesseSized, S: Storage, H: Storage> std::iter::IntoIterator
+    for &'a VecFile<T, S, H> {
     type Item = T;
-    type IntoIter = VecFileIterator<T>;
+    type IntoIter = VecFileIterator<'a, T, S, H>;
 
     fn into_iter(self) -> Self::IntoIter {
-        let file: &mut File = unsafe { transmute(self.file.as_ptr()) }; // Get file from cell
-        let mut file = file.try_clone().unwrap();
-        file.seek(SeekFrom::Start(0)).unwrap();
-        VecFileIterator {
-            file,
-            len: self.len,
-            counter: 0,
-            _phantom: PhantomData,
-        }
+        self.iter()
     }
 }
 
 
-impl<T: Desse + DesseSized> std::convert::TryFrom<Vec<T>> for VecFile<T> {
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::convert::TryFrom<Vec<T>>
+    for VecFile<T, S, H> {
     type Error = Box<dyn std::error::Error>;
     fn try_from(vec: Vec<T>) -> Result<Self, Self::Error> {
-        let mut ret = VecFile::new();
+        let mut ret = Self::default();
         ret.reserve(vec.len() as u64)?;
         ret.try_extend_from_slice(&vec)?;
         Ok(ret)
     }
 }
 
-impl<T: Desse + DesseSized> std::convert::TryInto<Vec<T>> for VecFile<T> {
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::convert::TryInto<Vec<T>>
+    for VecFile<T, S, H> {
     type Error = Box<dyn std::error::Error>;
     fn try_into(self) -> Result<Vec<T>, Self::Error> {
-        if self.len() > (std::usize::MAX as u64) {
-            return Err(Error::LenExceedsUsize(self.len()).into());
-        }
-
-        Ok((&self).into_iter().collect())
+        self.try_read_range(..)
     }
 }
 
 
 
-impl<T: Desse + DesseSized> std::iter::FromIterator<T> for VecFile<T> {
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::iter::FromIterator<T>
+    for VecFile<T, S, H> {
     fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> Self {
-        let mut vf = VecFile::new();
+        let mut vf = Self::default();
         vf.add_shadows(1).unwrap(); // To protect against potential read errors.
 
         for element in iter {
@@ -762,49 +1371,275 @@ impl<T: Desse + DesseSized> std::iter::FromIterator<T> for VecFile<T> {
 }
 
 
-impl<T: Desse + DesseSized + std::fmt::Debug> std::fmt::Debug for VecFile<T> {
+impl<T, S, H> std::fmt::Debug for VecFile<T, S, H>
+where T: Desse + DesseSized + std::fmt::Debug,
+      S: Storage,
+      H: Storage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut values_string = self
-                            .into_iter()
-                            .fold(String::new(), |acc, val| acc + &format!("{:?}, ", val));
-        values_string.truncate(values_string.len() - 2); // truncates the last ',' and space
-
-        write!(f, "[{}]", values_string)
+        f.debug_list().entries(self).finish()
     }
 }
 
 
 
 
-pub struct VecFileIterator<T: Desse + DesseSized> {
-    file: File,
-    len: u64,
-    counter: u64,
-    _phantom: PhantomData<T>,
+/// The default size, in bytes, of the blocks that VecFileIterator reads
+pub const DEFAULT_ITER_BUFFER_SIZE: usize = 64 * 1024;
+
+/// An iterator over the elements of a VecFile.
+///
+/// Rather than reading one element at a time, elements are read a block at a time and handed
+/// out from a buffer. Blocks start at multiples of the block size from the first element, and
+/// their size can be set with with_buffer_size.
+///
+/// The iterator can be used from both ends, and since the offset of any element is known,
+/// skipping elements with nth or nth_back doesn't read them.
+pub struct VecFileIterator<'a, T: Desse + DesseSized, S: Storage = File, H: Storage = S> {
+    vf: &'a VecFile<T, S, H>,
+    front: u64, // The index of the next element to read from the front
+    back: u64, // One past the index of the next element to read from the back
+    block_len: u64, // The number of elements in a block
+    front_buf: std::vec::IntoIter<T>, // Elements read from the front but not returned yet
+    back_buf: std::vec::IntoIter<T>, // Elements read from the back but not returned yet
 }
 
-impl<T: Desse + DesseSized> std::iter::Iterator for VecFileIterator<T> {
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.counter < self.len {
-           
-            // We re-seek on every iteration to prevent a bug where when iterating over two
-            // VecFiles at the same time, where both VecFiles are the same instance, the iterations
-            // would conflict. 
-            // Seeking every iteration sets the cursor to the right position even if another
-            // iterator over the same VecFile exists.
-            self.file.seek(SeekFrom::Start(self.counter * (std::mem::size_of::<T>() as u64))).unwrap();
+impl<'a, T: Desse + DesseSized, S: Storage, H: Storage> VecFileIterator<'a, T, S, H> {
+    fn new(vf: &'a VecFile<T, S, H>) -> Self {
+        Self::with_range(vf, 0, vf.len())
+    }
+
+    /// Creates an iterator over the elements from start up to end, which must be within vf's len.
+    fn with_range(vf: &'a VecFile<T, S, H>, start: u64, end: u64) -> Self {
+        Self {
+            vf,
+            front: start,
+            back: end,
+            block_len: 0,
+            front_buf: Vec::new().into_iter(),
+            back_buf: Vec::new().into_iter(),
+        }.with_buffer_size(DEFAULT_ITER_BUFFER_SIZE)
+    }
+
+    /// Sets the size, in bytes, of the blocks that are read at once. A block always holds at
+    /// least one element, so a size of 0 reads elements one at a time.
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.block_len = std::cmp::max(1, buffer_size / self.vf.element_size()) as u64;
+        self
+    }
+
+    /// Reads the elements from front up to the start of the next block into the front buffer.
+    fn fill_front(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let block_end = (self.front / self.block_len + 1).saturating_mul(self.block_len);
+        let count = std::cmp::min(block_end, self.back) - self.front;
+        self.front_buf = self.vf.read_many(self.front, count as usize)?.into_iter();
+        self.front += count;
+        Ok(())
+    }
+
+    /// Reads the elements from the start of the block that the back is in up to the back into
+    /// the back buffer.
+    fn fill_back(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let block_start = (self.back - 1) / self.block_len * self.block_len;
+        let start = std::cmp::max(block_start, self.front);
+        self.back_buf = self.vf.read_many(start, (self.back - start) as usize)?.into_iter();
+        self.back = start;
+        Ok(())
+    }
+
+    /// Ends the iteration, which is done after an error.
+    fn finish(&mut self) {
+        self.front = self.back;
+        self.front_buf = Vec::new().into_iter();
+        self.back_buf = Vec::new().into_iter();
+    }
 
+    /// Returns the next element, or the error from reading it. The iteration ends after an error.
+    fn next_result(&mut self) -> Option<Result<T, Box<dyn std::error::Error>>> {
+        if self.front_buf.len() == 0 && self.front < self.back {
+            if let Err(e) = self.fill_front() {
+                self.finish();
+                return Some(Err(e));
+            }
+        }
+        // Once the unread elements run out, the back's buffer holds whatever is left
+        self.front_buf.next().or_else(|| self.back_buf.next()).map(Ok)
+    }
+
+    /// Returns the next element from the back, or the error from reading it. The iteration ends
+    /// after an error.
+    fn next_back_result(&mut self) -> Option<Result<T, Box<dyn std::error::Error>>> {
+        if self.back_buf.len() == 0 && self.front < self.back {
+            if let Err(e) = self.fill_back() {
+                self.finish();
+                return Some(Err(e));
+            }
+        }
+        self.back_buf.next_back().or_else(|| self.front_buf.next_back()).map(Ok)
+    }
+
+    /// Skips n elements from the front without reading any that aren't already buffered.
+    fn skip_front(&mut self, n: u64) {
+        let n = skip_buffered(&mut self.front_buf, n, false);
+        let unread = self.back - self.front;
+        if n <= unread {
+            self.front += n;
+        }
+        else {
+            self.front = self.back;
+            skip_buffered(&mut self.back_buf, n - unread, false);
+        }
+    }
+
+    /// Skips n elements from the back without reading any that aren't already buffered.
+    fn skip_back(&mut self, n: u64) {
+        let n = skip_buffered(&mut self.back_buf, n, true);
+        let unread = self.back - self.front;
+        if n <= unread {
+            self.back -= n;
+        }
+        else {
+            self.back = self.front;
+            skip_buffered(&mut self.front_buf, n - unread, true);
+        }
+    }
+
+    /// The number of elements that are left
+    fn remaining(&self) -> u64 {
+        self.front_buf.len() as u64 + (self.back - self.front) + self.back_buf.len() as u64
+    }
+}
 
-            self.counter = self.counter + 1;
-            let mut buf = Vec::with_capacity(std::mem::size_of::<T>());
-            (&mut self.file).take(std::mem::size_of::<T>() as u64).read_to_end(&mut buf).unwrap();
-            Some(de_from(buf.as_slice()).unwrap())
+/// Drops up to n elements from the front, or back, of buf. Returns the number that couldn't be
+/// dropped because buf ran out.
+fn skip_buffered<T>(buf: &mut std::vec::IntoIter<T>, n: u64, from_back: bool) -> u64 {
+    let buffered = buf.len() as u64;
+    let skipped = std::cmp::min(n, buffered);
+    if skipped > 0 {
+        if from_back {
+            buf.nth_back(skipped as usize - 1);
         }
         else {
-            None
+            buf.nth(skipped as usize - 1);
         }
     }
+    n - skipped
+}
+
+/// Saturates n to a u64, which only matters on platforms where a usize can be larger.
+fn skip_count(n: usize) -> u64 {
+    u64::try_from(n).unwrap_or(u64::MAX)
+}
+
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::iter::Iterator
+    for VecFileIterator<'_, T, S, H> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_result().map(|element| element.unwrap())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        size_hint(self.remaining())
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.skip_front(skip_count(n));
+        self.next()
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+
+    fn count(self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::iter::DoubleEndedIterator
+    for VecFileIterator<'_, T, S, H> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.next_back_result().map(|element| element.unwrap())
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        self.skip_back(skip_count(n));
+        self.next_back()
+    }
+}
+
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::iter::ExactSizeIterator
+    for VecFileIterator<'_, T, S, H> {}
+
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::iter::FusedIterator
+    for VecFileIterator<'_, T, S, H> {}
+
+
+/// An iterator over the elements of a VecFile that returns an error rather than panicking when
+/// an element can't be read. Reads go through the same shadow replacement as try_get, so an error
+/// means the file and all of its shadows have failed.
+///
+/// The iteration ends after the first error.
+pub struct VecFileTryIterator<'a, T: Desse + DesseSized, S: Storage = File, H: Storage = S> {
+    iter: VecFileIterator<'a, T, S, H>,
+}
+
+impl<T: Desse + DesseSized, S: Storage, H: Storage> VecFileTryIterator<'_, T, S, H> {
+    /// Sets the size, in bytes, of the blocks that are read at once, see
+    /// VecFileIterator::with_buffer_size.
+    pub fn with_buffer_size(self, buffer_size: usize) -> Self {
+        Self { iter: self.iter.with_buffer_size(buffer_size) }
+    }
+}
+
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::iter::Iterator
+    for VecFileTryIterator<'_, T, S, H> {
+    type Item = Result<T, Box<dyn std::error::Error>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next_result()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.iter.skip_front(skip_count(n));
+        self.next()
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+
+    fn count(self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::iter::DoubleEndedIterator
+    for VecFileTryIterator<'_, T, S, H> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back_result()
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        self.iter.skip_back(skip_count(n));
+        self.next_back()
+    }
+}
+
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::iter::ExactSizeIterator
+    for VecFileTryIterator<'_, T, S, H> {}
+
+impl<T: Desse + DesseSized, S: Storage, H: Storage> std::iter::FusedIterator
+    for VecFileTryIterator<'_, T, S, H> {}
+
+/// The size hint for an iterator with remaining elements left.
+fn size_hint(remaining: u64) -> (usize, Option<usize>) {
+    match usize::try_from(remaining) {
+        Ok(remaining) => (remaining, Some(remaining)),
+        Err(_) => (usize::MAX, None),
+    }
 }
         
 
@@ -814,6 +1649,24 @@ impl<T: Desse + DesseSized> std::iter::Iterator for VecFileIterator<T> {
 
 
 
+/// Which copies a VecFile's reads are served from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadMode {
+    /// Reads come from the underlying file alone, and shadows are only read if it fails. This is
+    /// the default.
+    #[default]
+    Primary,
+    /// Every read comes from the underlying file and all of its shadows, and is whichever value
+    /// more than half of them agree on. Copies that fail the read, or their checksums, don't get
+    /// a vote. Shadows that are outvoted are overwritten with the majority's value, while an
+    /// underlying file that's outvoted or can't be read has failed, so it's replaced by a shadow.
+    ///
+    /// Reads return Error::NoMajority if no value has a majority, so this is best used with at
+    /// least two shadows, or with checksums. Every read is as many reads as there are copies.
+    Majority,
+}
+
+
 #[derive(Debug)]
 pub enum Error {
     OutOfRange(u64, u64),
@@ -824,6 +1677,21 @@ pub enum Error {
     InequalSizeForDe(usize, usize),
     RWTestFailedNotEqual([u8; 4], [u8; 4]),
     IrrecoverableState,
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    ElementSizeMismatch(u64, u64),
+    LenExceedsCap(u64, u64),
+    FileTooShort(u64, u64),
+    TypeTagMismatch(u64, u64),
+    ReadOnly,
+    InvalidRange(u64, u64),
+    ManifestFull,
+    InvalidManifest,
+    InvalidShadowPath(PathBuf),
+    ChecksumMismatch { block: u64 },
+    InvalidChecksumBlockSize(u64),
+    NoMajority,
+    DuplicateShadow(PathBuf),
 }
 
 impl std::fmt::Display for Error {
@@ -851,7 +1719,45 @@ impl std::fmt::Display for Error {
                        ),
             Error::IrrecoverableState => 
                 write!(f,
-        "No available shadows for replacement and the main file is in an irrecoverable state")
+        "No available shadows for replacement and the main file is in an irrecoverable state"),
+            Error::InvalidMagic =>
+                write!(f, "File is not a VecFile, its header is missing or invalid"),
+            Error::UnsupportedVersion(version) =>
+                write!(f, "Unsupported VecFile format version: {}", version),
+            Error::ElementSizeMismatch(found, expected) =>
+                write!(f, "Element size mismatch, File: {}; Expected: {}", found, expected),
+            Error::LenExceedsCap(len, cap) =>
+                write!(f, "Corrupt header, len exceeds cap: Length: {}; Capacity: {}", len, cap),
+            Error::FileTooShort(file_len, required_len) =>
+                write!(f, "File is too short for its header: Length: {}; Required: {}",
+                       file_len,
+                       required_len
+                       ),
+            Error::TypeTagMismatch(found, expected) =>
+                write!(f, "Type tag mismatch, File: {:#x}; Expected: {:#x}", found, expected),
+            Error::ReadOnly =>
+                write!(f, "The underlying file was opened read only"),
+            Error::InvalidRange(start, end) =>
+                write!(f, "Range starts after it ends: Start: {}; End: {}", start, end),
+            Error::ManifestFull =>
+                write!(f, "There's no room left in the header for another shadow path"),
+            Error::InvalidManifest =>
+                write!(f, "Corrupt header, the list of shadow paths is invalid"),
+            Error::InvalidShadowPath(path) =>
+                write!(f, "Shadow paths must be valid UTF-8: {}", path.display()),
+            Error::ChecksumMismatch { block } =>
+                write!(f, "Corrupt data, block {} doesn't match its checksum", block),
+            Error::InvalidChecksumBlockSize(block_size) =>
+                write!(f, "Checksum block size must be between 1 and {}: {}",
+                       MAX_CHECKSUM_BLOCK_SIZE,
+                       block_size
+                       ),
+            Error::NoMajority =>
+                write!(f, "The copies disagree, and no value is held by a majority of them"),
+            Error::DuplicateShadow(path) =>
+                write!(f, "The file is already the underlying file or one of its shadows: {}",
+                       path.display()
+                       ),
         }
     }
 }
@@ -867,37 +1773,75 @@ pub(crate) fn de_from<T: Desse + DesseSized>(buf: &[u8]) -> Result<T, Box<dyn st
         return Err(Error::InequalSizeForDe(buf.len(), se_size).into());
     }
 
-    // We know the size of <T as Desse>::Output, and we know it's a u8 array of that
-    // size, so even though the compilier doesn't know that, we can use transmute to treat it
-    // as such. This should always be safe as long as <T as Desse>::Output is a array of u8.
+    // <T as Desse>::Output is a u8 array of se_size bytes, but the compiler can't see that
+    // through the associated type, so buf's pointer is cast to a reference to one. This is
+    // sound since the length check above means buf holds exactly that many bytes, and a u8
+    // array has no alignment or validity requirements beyond that.
     unsafe {
-        Ok(T::deserialize_from(std::mem::transmute(buf.as_ptr())))
+        Ok(T::deserialize_from(&*(buf.as_ptr() as *const <T as Desse>::Output)))
    }
 }
 
+/// Serializes every value into a single buffer, one after another.
+pub(crate) fn ser_many<'a, T, U>(values: U) -> Result<Vec<u8>, Box<dyn std::error::Error>>
+where T: Desse + DesseSized + 'a,
+      U: IntoIterator<Item=&'a T> {
+    let mut buf = Vec::new();
+    for value in values {
+        buf.extend_from_slice(&ser_to(value)?);
+    }
+    Ok(buf)
+}
+
 pub(crate) fn ser_to<T: Desse + DesseSized>(value: &T) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let se_size = std::mem::size_of::<<T as Desse>::Output>();
     let val_ser = value.serialize();
     
-    // We know the size of <T as Desse>::Output, and we know it's a u8 array of that
-    // size, so even though the compilier doesn't know that, we can use transmute to treat it
-    // as such. This should always be safe as long as <T as Desse>::Output is a array of u8.
+    // <T as Desse>::Output is a u8 array of se_size bytes, but the compiler can't see that
+    // through the associated type, so it's read through a pointer cast to a u8 pointer instead.
+    // This is sound since val_ser is exactly se_size initialized bytes, and the slice is copied
+    // out before val_ser is dropped.
     unsafe {
-        let ptr: * const u8 = std::mem::transmute(&val_ser as * const _);
+        let ptr: * const u8 = &val_ser as * const _ as * const u8;
         Ok(std::slice::from_raw_parts(ptr, se_size).to_vec())
     }
 
 
 }
 
-/// Tests reading and writing to the specified file, and returns it if it passes
-pub(crate) fn rw_test(file: &mut File) -> Result<(), Box<dyn std::error::Error>> {
+/// Makes path absolute, so it can be found again from any working directory. The file it names
+/// doesn't need to exist yet, but its directory does.
+fn absolute_path(path: &Path) -> std::io::Result<PathBuf> {
+    let file_name = path.file_name().ok_or(std::io::ErrorKind::InvalidInput)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Ok(std::fs::canonicalize(dir)?.join(file_name))
+}
+
+/// Whether the two paths lead to the same file, which can only be the case if it exists.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Hashes the given bytes with 64 bit FNV-1a. This is used over std's hashers since its output is
+/// stable, which matters for anything that ends up in a file.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Tests reading and writing to the specified storage, and returns it if it passes
+pub(crate) fn rw_test<S: Storage>(storage: &mut S) -> Result<(), Box<dyn std::error::Error>> {
     let buf_in = [0, 3, 6, 1];
     let mut buf_out = [0, 0, 0, 0];
-    file.write_all(&buf_in)?;
-    file.seek(SeekFrom::Start(0))?;
-    file.read_exact(&mut buf_out)?;
-    file.seek(SeekFrom::Start(0))?; // Reset seek to the beginning
+    storage.write_all_at(&buf_in, 0)?;
+    storage.read_exact_at(&mut buf_out, 0)?;
     if buf_in == buf_out {
         Ok(())
     }
@@ -907,15 +1851,37 @@ pub(crate) fn rw_test(file: &mut File) -> Result<(), Box<dyn std::error::Error>>
 
 }
 
-/// Continually generates tempfiles until one passes the rw test.
-/// They will pass the first time a vast majority of the time, but in case of some underlying OS
-/// error, we can grab a new one and test and so on.
-pub(crate) fn tested_tempfile() -> File {
+/// Generates temporary storage, in the given directory if there is one, until one passes the rw
+/// test. They will pass the first time a vast majority of the time, but in case of some underlying
+/// OS error, we can grab a new one and test and so on.
+///
+/// A given directory may be missing or unwritable, and some storage may never work, so rather
+/// than trying forever this gives up after a few attempts and returns the last error.
+pub(crate) fn tested_temp<S: Storage>(dir: Option<&Path>) -> Result<S, Box<dyn std::error::Error>> {
+    tested_temp_with(dir, |_| ())
+}
+
+/// Generates temporary storage like tested_temp, passing the error from each failed attempt that
+/// will be retried to on_retry.
+pub(crate) fn tested_temp_with<S, F>(dir: Option<&Path>, mut on_retry: F)
+    -> Result<S, Box<dyn std::error::Error>>
+where S: Storage,
+      F: FnMut(&dyn std::error::Error) {
+    let max_attempts = 5;
+    let mut attempts = 1;
     loop {
-        if let Ok(mut file) = tempfile::tempfile() {
-            if let Ok(_) = rw_test(&mut file) {
-                file.set_len(0).unwrap();
-                break file;
+        let result = S::temp(dir)
+                        .map_err(|e| e.into())
+                        .and_then(|mut storage| rw_test(&mut storage).map(|_| storage));
+        match result {
+            Ok(mut storage) => {
+                storage.set_len(0)?;
+                break Ok(storage);
+            }
+            Err(e) if attempts == max_attempts => break Err(e),
+            Err(e) => {
+                on_retry(&*e);
+                attempts += 1;
             }
         }
     }
@@ -927,6 +1893,8 @@ pub(crate) fn tested_tempfile() -> File {
 mod tests {
     use super::*;
     use std::convert::TryInto;
+    use crate::storage::MmapStorage;
+    use crate::faulty::FaultyStorage;
 
     #[test]
     fn push_pop() {
@@ -991,7 +1959,7 @@ mod tests {
         let orig_values: Vec<u16> = vec![0x2222, 0xffff, 0xdddd, 0xaaaa, 0x8888];
         let mut f: VecFile<u16> = VecFile::new();
         f.extend_from_slice(orig_values.as_slice());
-        for (orig, arr_file) in orig_values.into_iter().zip(f.into_iter()) {
+        for (orig, arr_file) in orig_values.into_iter().zip(&f) {
             assert_eq!(orig, arr_file);
         }
     }
@@ -1000,7 +1968,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn index_out_of_bounds() {
-        let mut f: VecFile<u16> = vec![0x2222, 0xffff, 0xdddd, 0xaaaa].try_into().unwrap();
+        let f: VecFile<u16> = vec![0x2222, 0xffff, 0xdddd, 0xaaaa].try_into().unwrap();
         f.get(4);
     }
 
@@ -1044,7 +2012,8 @@ mod tests {
         assert_eq!(vec, vecf.into_iter().collect::<Vec<_>>());
         assert_eq!(vec.remove(3), vecf.remove(3));
         assert_eq!(vec, vecf.into_iter().collect::<Vec<_>>());
-        assert_eq!(vec.insert(3, 123), vecf.insert(3, &123));
+        vec.insert(3, 123);
+        vecf.insert(3, &123);
         assert_eq!(vec, vecf.into_iter().collect::<Vec<_>>());
 
         let mut vec = orig.clone();
@@ -1053,6 +2022,47 @@ mod tests {
         vec.insert(7, 100);
         assert_eq!(vecf, vec);
 
+        // Inserting at the len appends, like Vec
+        vecf.insert(9, &200);
+        vec.insert(9, 200);
+        assert_eq!(vecf, vec);
+        assert!(vecf.try_insert(11, &300).is_err());
+
+        let mut other = vecf.split_off(4);
+        assert_eq!(other, vec.split_off(4));
+        assert_eq!(vecf, vec);
+        assert!(vecf.try_split_off(5).is_err());
+        assert!(other.split_off(6).is_empty());
+
+    }
+
+    #[test]
+    fn insert_slice_remove_range() {
+        // Enough elements that shifting takes several blocks
+        let mut vec: Vec<u64> = (0..20_000).collect();
+        let mut vf: VecFile<u64> = vec.clone().try_into().unwrap();
+        vf.add_shadows(1).unwrap();
+
+        let slice: Vec<u64> = (100_000..100_500).collect();
+        vf.insert_slice(3, &slice);
+        vec.splice(3..3, slice.iter().cloned());
+        vf.insert_slice(19_000, &slice[..7]);
+        vec.splice(19_000..19_000, slice[..7].iter().cloned());
+        vf.insert_slice(0, &[]);
+        vf.insert(0, &7);
+        vec.insert(0, 7);
+        assert_eq!(vf, vec);
+
+        assert_eq!(vf.remove_range(10..9_000), vec.drain(10..9_000).collect::<Vec<_>>());
+        assert_eq!(vf.remove_range(..=1), vec.drain(..=1).collect::<Vec<_>>());
+        assert_eq!(vf.remove_range(11_000..), vec.drain(11_000..).collect::<Vec<_>>());
+        assert!(vf.remove_range(5..5).is_empty());
+        assert_eq!(vf, vec);
+        assert!(vf.confirm_shadow_equivalence().unwrap());
+
+        assert!(vf.try_remove_range(0..=vec.len() as u64).is_err());
+        assert!(vf.try_insert_slice(vec.len() as u64 + 1, &[1]).is_err());
+        assert_eq!(vf, vec);
     }
 
     #[test]
@@ -1098,12 +2108,378 @@ mod tests {
         assert_eq!(vecf, vec);
         assert_eq!(vec, vecf.into_iter().collect::<Vec<u32>>());
         assert!(vecf.confirm_shadow_equivalence().unwrap());
-        vec.truncate(0);
+        vec.clear();
         vecf.truncate(0);
         assert_eq!(vec, vecf.into_iter().collect::<Vec<u32>>());
         assert!(vecf.confirm_shadow_equivalence().unwrap());
     }
 
+    #[test]
+    fn iterator_buffers() {
+        let vec: Vec<u32> = (0..1000).collect();
+        let vf: VecFile<u32> = vec.iter().cloned().collect();
+        for buffer_size in [0, 3, 4, 100, 4000, 4004, 1 << 20] {
+            assert!(vf.iter().with_buffer_size(buffer_size).eq(vec.iter().cloned()));
+        }
+
+        let empty: VecFile<u32> = VecFile::new();
+        assert_eq!(empty.iter().next(), None);
+    }
+
+    // Rust pads this out to 16 bytes in memory, but it's serialized in 11
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Desse, DesseSized)]
+    struct Padded {
+        a: u8,
+        b: u64,
+        c: u16,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Desse, DesseSized)]
+    enum Shape {
+        Point,
+        Circle(u32),
+        Rect { w: u16, h: u8 },
+    }
+
+    #[test]
+    fn padded_elements() {
+        let vec: Vec<Padded> = (0..300u16)
+            .map(|i| Padded { a: i as u8, b: u64::from(i) << 40, c: i })
+            .collect();
+        let mut vf: VecFile<Padded> = vec.iter().cloned().collect();
+        assert_eq!(vf.element_size(), 11);
+        assert_ne!(vf.element_size(), std::mem::size_of::<Padded>());
+
+        assert_eq!(vf.get(123), vec[123]);
+        assert!(vf.iter().eq(vec.iter().cloned()));
+        for buffer_size in [0, 11, 12, 100, 4096] {
+            assert!(vf.iter().with_buffer_size(buffer_size).eq(vec.iter().cloned()));
+        }
+        assert_eq!(vf.clone(), vec);
+
+        let mut vec = vec;
+        vf.set(5, &Padded { a: 1, b: 2, c: 3 });
+        vec[5] = Padded { a: 1, b: 2, c: 3 };
+        assert_eq!(vf.remove(7), vec.remove(7));
+        let vec_out: Vec<Padded> = vf.try_into().unwrap();
+        assert_eq!(vec_out, vec);
+    }
+
+    #[test]
+    fn enum_elements() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("enum_elements.bin");
+        let vec = vec![Shape::Point, Shape::Circle(7), Shape::Rect { w: 300, h: 2 }, Shape::Point];
+        let mut vf: VecFile<Shape> = VecFile::new_with_path(&path).unwrap();
+        vf.extend_from_slice(&vec);
+        assert_eq!(vf, vec);
+        std::mem::drop(vf);
+
+        let vf: VecFile<Shape> = VecFile::open(&path).unwrap();
+        assert_eq!(vf.element_size(), std::mem::size_of::<<Shape as Desse>::Output>());
+        assert!(vf.iter().eq(vec.iter().cloned()));
+        assert_eq!(vf.get(2), Shape::Rect { w: 300, h: 2 });
+    }
+
+    /// In-memory storage that counts the bytes read from it and the writes to it.
+    #[derive(Default)]
+    struct TestStorage {
+        bytes: Vec<u8>,
+        bytes_read: std::sync::Arc<std::sync::atomic::AtomicU64>,
+        writes: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    }
+
+    impl Storage for TestStorage {
+        fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+            self.bytes_read.fetch_add(buf.len() as u64, std::sync::atomic::Ordering::SeqCst);
+            self.bytes.read_exact_at(buf, offset)
+        }
+        fn write_all_at(&mut self, buf: &[u8], offset: u64) -> std::io::Result<()> {
+            self.writes.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.bytes.write_all_at(buf, offset)
+        }
+        fn set_len(&mut self, len: u64) -> std::io::Result<()> {
+            Storage::set_len(&mut self.bytes, len)
+        }
+        fn size(&self) -> std::io::Result<u64> {
+            self.bytes.size()
+        }
+        fn sync(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+        fn temp(_dir: Option<&Path>) -> std::io::Result<Self> {
+            Ok(Self::default())
+        }
+    }
+
+    #[test]
+    fn try_iter() {
+        let storage = FaultyStorage::new(Vec::new());
+        let faults = storage.faults();
+        let mut vf: VecFile<u32, FaultyStorage, Vec<u8>> = VecFileBuilder::new()
+                                                               .shadows(1)
+                                                               .build_in(storage)
+                                                               .unwrap();
+        vf.extend_from_slice(&(0..100).collect::<Vec<_>>());
+        assert!(vf.try_iter().map(|e| e.unwrap()).eq(0..100));
+
+        // A read part way through fails, and the shadow takes over
+        faults.fail_nth_read(2);
+        assert!(vf.try_iter().with_buffer_size(40).map(|e| e.unwrap()).eq(0..100));
+        assert!(vf.iter().eq(0..100));
+
+        // Without any shadows, the error is returned once and the iteration ends
+        let storage = FaultyStorage::new(Vec::new());
+        let faults = storage.faults();
+        let mut vf: VecFile<u32, FaultyStorage> = VecFileBuilder::new().build_in(storage).unwrap();
+        vf.extend_from_slice(&(0..100).collect::<Vec<_>>());
+        faults.fail_reads(true);
+        let mut iter = vf.try_iter();
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+        assert!(vf.try_get(0).is_err());
+    }
+
+    #[test]
+    fn double_ended() {
+        let vec: Vec<u32> = (0..1000).collect();
+        let vf: VecFile<u32> = vec.iter().cloned().collect();
+        for buffer_size in [0, 4, 12, 400, 1 << 20] {
+            let iter = || vf.iter().with_buffer_size(buffer_size);
+            assert!(iter().rev().eq(vec.iter().rev().cloned()));
+            assert_eq!(iter().len(), 1000);
+            assert_eq!(iter().last(), Some(999));
+
+            // Both ends meeting in the middle of a block
+            let mut vf_iter = iter();
+            let mut vec_iter = vec.iter().cloned();
+            for i in 0..1100 {
+                if i % 3 == 0 {
+                    assert_eq!(vf_iter.next_back(), vec_iter.next_back());
+                }
+                else {
+                    assert_eq!(vf_iter.next(), vec_iter.next());
+                }
+                assert_eq!(vf_iter.len(), vec_iter.len());
+            }
+
+            let mut vf_iter = iter();
+            let mut vec_iter = vec.iter().cloned();
+            for n in [0, 1, 5, 100, 0, 37, 1] {
+                assert_eq!(vf_iter.nth(n), vec_iter.nth(n));
+                assert_eq!(vf_iter.nth_back(n * 2), vec_iter.nth_back(n * 2));
+                assert_eq!(vf_iter.len(), vec_iter.len());
+            }
+            assert!(vf_iter.eq(vec_iter));
+
+            let mut vf_iter = iter();
+            assert_eq!(vf_iter.nth_back(998), Some(1));
+            assert_eq!(vf_iter.nth(1), None);
+            assert_eq!(vf_iter.next_back(), None);
+            assert!(iter().skip(990).eq(990..1000));
+            assert!(vf.try_iter().with_buffer_size(buffer_size).rev().skip(10).step_by(7)
+                      .map(|e| e.unwrap())
+                      .eq(vec.iter().rev().skip(10).step_by(7).cloned()));
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let vec: Vec<u16> = (0..1000).collect();
+        let vf: VecFile<u16> = vec.iter().cloned().collect();
+
+        assert_eq!(vf.read_range(10..20), &vec[10..20]);
+        assert_eq!(vf.read_range(..=5), &vec[..=5]);
+        assert_eq!(vf.read_range(990..), &vec[990..]);
+        assert_eq!(vf.read_range(..), vec);
+        assert!(vf.read_range(1000..).is_empty());
+        assert_eq!(vf.read_range((Bound::Excluded(3), Bound::Included(6))), &vec[4..=6]);
+        assert!(vf.iter_range(100..200).eq(100..200));
+        assert!(vf.iter_range(100..200).with_buffer_size(6).rev().eq((100..200).rev()));
+        assert_eq!(vf.iter_range(..=10).len(), 11);
+
+        let mut buf = [0u16; 50];
+        vf.read_into(500, &mut buf);
+        assert_eq!(buf, vec[500..550]);
+        vf.read_into(0, &mut []);
+
+        assert!(vf.try_read_range(0..1001).is_err());
+        assert!(vf.try_read_range(1001..).is_err());
+        assert!(vf.try_read_range(..=u64::MAX).is_err());
+        let err = vf.try_read_range((Bound::Included(20), Bound::Excluded(10))).unwrap_err();
+        match err.downcast_ref::<Error>() {
+            Some(Error::InvalidRange(20, 10)) => (),
+            e => panic!("Unexpected result: {:?}", e),
+        }
+        assert!(vf.try_read_into(990, &mut buf).is_err());
+        assert!(std::panic::catch_unwind(|| vf.iter_range(0..1001)).is_err());
+    }
+
+    #[test]
+    fn bulk_writes() {
+        let storage = TestStorage::default();
+        let writes = storage.writes.clone();
+        let mut vf: VecFile<u32, TestStorage, Vec<u8>> = VecFileBuilder::new()
+                                                               .shadows(2)
+                                                               .capacity(1000)
+                                                               .build_in(storage)
+                                                               .unwrap();
+        let mut vec: Vec<u32> = (0..1000).collect();
+        let writes = || writes.swap(0, std::sync::atomic::Ordering::SeqCst);
+        writes();
+
+        // One write for the elements and one for the len
+        vf.extend_from_slice(&vec);
+        assert_eq!(writes(), 2);
+
+        vf.write_slice_at(100, &[1, 2, 3]);
+        vec[100..103].copy_from_slice(&[1, 2, 3]);
+        assert_eq!(writes(), 1);
+        vf.set_from_iter(990, &[9; 10]);
+        vec[990..].copy_from_slice(&[9; 10]);
+        vf.set_from_iter(0, [5; 10].iter().step_by(2));
+        vec[..5].copy_from_slice(&[5; 5]);
+        vf.write_slice_at(1000, &[]);
+        assert_eq!(writes(), 3);
+        assert_eq!(vf, vec);
+        assert!(vf.confirm_shadow_equivalence().unwrap());
+
+        // Nothing is written when any element is out of range
+        assert!(vf.try_write_slice_at(999, &[7, 7]).is_err());
+        assert!(vf.try_write_slice_at(1001, &[]).is_err());
+        assert!(vf.try_set_from_iter(u64::MAX, &[7]).is_err());
+        assert_eq!(writes(), 0);
+        assert_eq!(vf, vec);
+
+        vf.read_only = true;
+        assert!(vf.try_write_slice_at(0, &[7]).is_err());
+        assert!(vf.try_extend_from_slice(&[7]).is_err());
+        assert_eq!(vf, vec);
+    }
+
+    #[test]
+    fn skipping_reads_nothing() {
+        let storage = TestStorage::default();
+        let bytes_read = storage.bytes_read.clone();
+        let mut vf: VecFile<u32, TestStorage> = VecFileBuilder::new().build_in(storage).unwrap();
+        vf.extend_from_slice(&(0..100_000).collect::<Vec<_>>());
+        let bytes_read = || bytes_read.swap(0, std::sync::atomic::Ordering::SeqCst);
+        bytes_read();
+
+        assert!(vf.iter().with_buffer_size(0).rev().take(100).eq((99_900..100_000).rev()));
+        assert_eq!(bytes_read(), 400);
+        assert_eq!(vf.iter().with_buffer_size(40).nth(50_000), Some(50_000));
+        assert_eq!(bytes_read(), 40);
+        assert_eq!(vf.iter().with_buffer_size(40).nth_back(50_000), Some(49_999));
+        assert_eq!(bytes_read(), 40);
+        assert_eq!(vf.iter().len(), 100_000);
+        assert_eq!(vf.iter().count(), 100_000);
+        assert_eq!(bytes_read(), 0);
+    }
+
+    #[test]
+    fn interleaved_reads() {
+        let vf: VecFile<u64> = (0..100).collect();
+        let mut forward = vf.into_iter();
+        let mut skipped = vf.into_iter().skip(50);
+        for i in 0..50 {
+            assert_eq!(forward.next(), Some(i));
+            assert_eq!(vf.get(99 - i), 99 - i);
+            assert_eq!(skipped.next(), Some(i + 50));
+        }
+        assert_eq!(skipped.next(), None);
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<VecFile<u64>>();
+        assert_send_sync::<VecFile<u64, MmapStorage, Vec<u8>>>();
+        assert_send_sync::<VecFileIterator<u64>>();
+
+        let vf: VecFile<u64> = (0..100).collect();
+        let vf = std::thread::spawn(move || {
+            assert_eq!(vf.get(42), 42);
+            vf
+        }).join().unwrap();
+        assert_eq!(vf.len(), 100);
+    }
+
+    #[test]
+    fn parallel_readers() {
+        let vf: VecFile<u64> = (0..1000).collect();
+        vf.add_shadows(1).unwrap();
+
+        std::thread::scope(|scope| {
+            for t in 0..8 {
+                let vf = &vf;
+                scope.spawn(move || {
+                    for _ in 0..4 {
+                        for i in (t..1000).step_by(8) {
+                            assert_eq!(vf.get(i), i);
+                        }
+                        assert!(vf.into_iter().eq(0..1000));
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn parallel_readers_one_writer() {
+        use std::sync::{Arc, RwLock};
+
+        let vf: Arc<RwLock<VecFile<u64>>> = Arc::new(RwLock::new(VecFile::new()));
+        let writer = {
+            let vf = vf.clone();
+            std::thread::spawn(move || {
+                for i in 0..500 {
+                    let mut vf = vf.write().unwrap();
+                    vf.push(&(i * 3));
+                    if i % 50 == 0 {
+                        vf.set(i / 2, &(i / 2 * 3));
+                    }
+                }
+            })
+        };
+
+        let readers: Vec<_> = (0..4).map(|_| {
+            let vf = vf.clone();
+            std::thread::spawn(move || {
+                let mut last_len = 0;
+                while last_len < 500 {
+                    let vf = vf.read().unwrap();
+                    assert!(vf.len() >= last_len);
+                    last_len = vf.len();
+                    // Every element the writer has pushed so far is visible and intact
+                    for (i, element) in vf.into_iter().enumerate() {
+                        assert_eq!(element, i as u64 * 3);
+                    }
+                }
+            })
+        }).collect();
+
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(vf.read().unwrap().len(), 500);
+    }
+
+    #[test]
+    fn shadows_while_iterating() {
+        let vf: VecFile<u32> = (0..10).collect();
+        for (i, element) in vf.into_iter().enumerate() {
+            if i == 5 {
+                // Shadows can be added through a shared reference, even while it's being read
+                vf.add_shadows(2).unwrap();
+            }
+            assert_eq!(element, i as u32);
+        }
+        assert_eq!(vf.shadow_count(), 2);
+        assert_eq!(vf.clone().shadow_count(), 2);
+    }
+
     #[test]
     fn clone() {
         let vf: VecFile<u8> = (vec![5u8, 1, 3, 9]).try_into().unwrap();
@@ -1111,6 +2487,323 @@ mod tests {
         assert_eq!(vf, vf_clone);
     }
 
+    #[test]
+    fn reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reopen.bin");
+        {
+            let mut f: VecFile<u32> = VecFile::new_with_path(&path).unwrap();
+            f.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+            f.pop();
+            f.set(0, &100);
+        }
+
+        let mut f: VecFile<u32> = VecFile::open(&path).unwrap();
+        assert_eq!(f.len(), 9);
+        assert_eq!(f.cap(), 16);
+        assert_eq!(f, vec![100, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        f.truncate(3);
+        f.push(&42);
+        std::mem::drop(f);
+
+        let f: VecFile<u32> = VecFile::open(&path).unwrap();
+        assert_eq!(f, vec![100, 2, 3, 42]);
+        assert_eq!(f.len(), 4);
+    }
+
+    #[test]
+    fn checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checksums.bin");
+        {
+            let mut f: VecFile<u32> = VecFileBuilder::new().create(true)
+                                                           .checksums(true)
+                                                           .checksum_block_size(64)
+                                                           .open(&path)
+                                                           .unwrap();
+            assert!(f.has_checksums());
+            f.extend_from_slice(&(0..1000).collect::<Vec<_>>());
+            f.insert(0, &1000);
+            f.remove(0);
+
+            // Files that are made from it are checksummed too
+            let clone = f.clone();
+            assert!(clone.has_checksums());
+            assert_eq!(clone, f);
+            let other = f.split_off(900);
+            assert!(other.has_checksums());
+            assert!(other.iter().eq(900..1000));
+        }
+
+        // The checksums outlive the file, and anything else that's opened doesn't get them
+        let f: VecFile<u32> = VecFile::open(&path).unwrap();
+        assert!(f.has_checksums());
+        assert!(f.iter().eq(0..900));
+        std::mem::drop(f);
+        assert!(!VecFile::<u32>::new().has_checksums());
+
+        // Flip a bit in the element at index 500, which is in block 31 with 16 elements a block
+        let mut file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        let mut byte = [0u8];
+        file.read_exact_at(&mut byte, HEADER_SIZE + 4 * 500).unwrap();
+        byte[0] ^= 1;
+        file.write_all_at(&byte, HEADER_SIZE + 4 * 500).unwrap();
+
+        let f: VecFile<u32> = VecFile::open(&path).unwrap();
+        let e = f.try_get(500).unwrap_err();
+        assert!(matches!(e.downcast_ref::<Error>(), Some(Error::ChecksumMismatch { block: 31 })));
+        assert!(f.try_get(496).is_err());
+        assert_eq!(f.get(495), 495);
+        assert_eq!(f.get(512), 512);
+
+        let result: Result<VecFile<u32>, _> = VecFileBuilder::new().checksums(true)
+                                                                   .checksum_block_size(0)
+                                                                   .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn capacity_overflow() {
+        let result = VecFileBuilder::new().capacity(u64::MAX / 4).build::<u64, Vec<u8>, Vec<u8>>();
+        assert!(matches!(result.unwrap_err().downcast_ref(), Some(Error::IndexExceedsMaxU64)));
+
+        let mut vf: VecFile<u64, Vec<u8>> = VecFileBuilder::new().build().unwrap();
+        vf.push(&1);
+        let result = vf.reserve(u64::MAX);
+        assert!(matches!(result.unwrap_err().downcast_ref(), Some(Error::IndexExceedsMaxU64)));
+        assert_eq!(vf, vec![1]);
+
+        // Doubling a cap this large would overflow, without needing to allocate it first
+        let storage = vec![0u8; HEADER_SIZE as usize];
+        let mut vf: VecFile<u64, Vec<u8>> = unsafe {
+            VecFile::from_raw_parts(storage, 0, u64::MAX / 8)
+        };
+        let result = vf.reserve(u64::MAX / 8 + 1);
+        assert!(matches!(result.unwrap_err().downcast_ref(), Some(Error::IndexExceedsMaxU64)));
+        assert_eq!(vf.cap(), u64::MAX / 8);
+    }
+
+    #[test]
+    fn named_shadows() {
+        let dir = tempfile::tempdir().unwrap();
+        let shadow_dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("named_shadows.bin");
+        let shadow_paths: Vec<PathBuf> = (0..2).map(|i| {
+            std::fs::canonicalize(shadow_dir.path()).unwrap().join(format!("shadow{}.bin", i))
+        }).collect();
+        {
+            let mut f: VecFile<u32> = VecFile::new_with_path(&path).unwrap();
+            f.add_shadows(1).unwrap();
+            f.extend_from_slice(&[1, 2, 3]);
+            f.add_shadow_at(&shadow_paths[0]).unwrap();
+            f.add_shadow_at(&shadow_paths[1]).unwrap();
+            f.push(&4);
+            assert_eq!(f.shadow_count(), 3);
+            assert_eq!(f.shadow_paths(), shadow_paths);
+        }
+
+        // Only the named shadows come back, already up to date
+        let mut f: VecFile<u32> = VecFile::open(&path).unwrap();
+        assert_eq!(f.shadow_count(), 2);
+        assert_eq!(f.shadow_paths(), shadow_paths);
+        assert!(f.confirm_shadow_equivalence().unwrap());
+        f.push(&5);
+        std::mem::drop(f);
+
+        // Each shadow is a full copy that can be opened in place of the file, and the file's
+        // other shadows come along with it
+        let f: VecFile<u32> = VecFile::open(&shadow_paths[0]).unwrap();
+        assert_eq!(f, vec![1, 2, 3, 4, 5]);
+        assert_eq!(f.shadow_paths(), &shadow_paths[1..]);
+        std::mem::drop(f);
+
+        // Shadows that have gone missing stay in the header, to be tried again next time
+        std::fs::remove_file(&shadow_paths[0]).unwrap();
+        let mut f: VecFile<u32> = VecFile::open(&path).unwrap();
+        assert_eq!(f.shadow_paths(), &shadow_paths[1..]);
+        assert_eq!(f.missing_shadow_paths(), &shadow_paths[..1]);
+        assert_eq!(f.health().missing_shadows, 1);
+        assert_eq!(f, vec![1, 2, 3, 4, 5]);
+        f.remove_shadows(1);
+        std::mem::drop(f);
+        let f: VecFile<u32> = VecFile::open(&path).unwrap();
+        assert_eq!(f.shadow_count(), 0);
+        assert_eq!(f.missing_shadow_paths(), &shadow_paths[..1]);
+
+        // Adding a missing shadow back lists it as a shadow again
+        f.add_shadow_at(&shadow_paths[0]).unwrap();
+        assert!(f.missing_shadow_paths().is_empty());
+        std::mem::drop(f);
+        let f: VecFile<u32> = VecFile::open(&path).unwrap();
+        assert_eq!(f.shadow_paths(), &shadow_paths[..1]);
+        assert!(f.missing_shadow_paths().is_empty());
+        std::mem::drop(f);
+
+        // Read only files leave their shadows alone
+        let f: VecFile<u32> = VecFileBuilder::new().read_only(true).open(&path).unwrap();
+        assert_eq!(f.shadow_count(), 0);
+        assert!(f.add_shadow_at(&shadow_paths[1]).is_err());
+
+        // Shadows can't be named when they aren't backed by files
+        let f: VecFile<u32, File, Vec<u8>> = VecFileBuilder::new().build().unwrap();
+        assert!(f.add_shadow_at(&shadow_paths[1]).is_err());
+        assert_eq!(f.shadow_count(), 0);
+    }
+
+    #[test]
+    fn duplicate_named_shadows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("duplicate_shadows.bin");
+        let shadow_path = dir.path().join("shadow.bin");
+        let mut f: VecFile<u32> = VecFile::new_with_path(&path).unwrap();
+        f.extend_from_slice(&[1, 2, 3]);
+        f.add_shadow_at(&shadow_path).unwrap();
+
+        // Neither the file itself nor a shadow it already has can be added, however the path is
+        // spelled
+        let duplicates = [path.clone(),
+                          shadow_path.clone(),
+                          dir.path().join(".").join("shadow.bin")];
+        for duplicate in &duplicates {
+            let e = f.add_shadow_at(duplicate).unwrap_err();
+            assert!(matches!(e.downcast_ref::<Error>(), Some(Error::DuplicateShadow(_))));
+        }
+        assert_eq!(f.shadow_count(), 1);
+        assert_eq!(f, vec![1, 2, 3]);
+        assert!(f.confirm_shadow_equivalence().unwrap());
+
+        // Nor can the file it's been moved to
+        let new_path = dir.path().join("duplicate_shadows_new.bin");
+        f.to_named_file(&new_path).unwrap();
+        assert!(f.add_shadow_at(&new_path).is_err());
+        f.add_shadow_at(&path).unwrap();
+        assert_eq!(f.shadow_count(), 2);
+
+        // Missing shadows are forgotten once the shadows are cleared
+        std::mem::drop(f);
+        std::fs::remove_file(&shadow_path).unwrap();
+        let mut f: VecFile<u32> = VecFile::open(&new_path).unwrap();
+        assert_eq!(f.missing_shadow_paths().len(), 1);
+        f.clear_shadows();
+        std::mem::drop(f);
+        let f: VecFile<u32> = VecFile::open(&new_path).unwrap();
+        assert_eq!(f.shadow_count(), 0);
+        assert!(f.missing_shadow_paths().is_empty());
+    }
+
+    #[test]
+    fn named_shadows_to_named_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("named_shadows_orig.bin");
+        let new_path = dir.path().join("named_shadows_new.bin");
+        let shadow_path = std::fs::canonicalize(dir.path()).unwrap().join("shadow.bin");
+        {
+            let mut f: VecFile<u32> = VecFile::new_with_path(&path).unwrap();
+            f.extend_from_slice(&[1, 2, 3]);
+            f.add_shadow_at(&shadow_path).unwrap();
+            f.to_named_file(&new_path).unwrap();
+            f.push(&4);
+            assert_eq!(f.shadow_paths(), vec![shadow_path.clone()]);
+        }
+
+        // The shadow went with the new file, so reopening the original leaves it alone
+        let f: VecFile<u32> = VecFile::open(&path).unwrap();
+        assert_eq!(f.shadow_count(), 0);
+        assert_eq!(f, vec![1, 2, 3]);
+        std::mem::drop(f);
+        let f: VecFile<u32> = VecFile::open(&shadow_path).unwrap();
+        assert_eq!(f, vec![1, 2, 3, 4]);
+        std::mem::drop(f);
+
+        let mut f: VecFile<u32> = VecFile::open(&new_path).unwrap();
+        assert_eq!(f.shadow_paths(), vec![shadow_path.clone()]);
+        assert_eq!(f, vec![1, 2, 3, 4]);
+        assert!(f.confirm_shadow_equivalence().unwrap());
+    }
+
+    #[test]
+    fn type_tags() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("type_tags.bin");
+        let mut f: VecFile<u32> = VecFile::new_with_path(&path).unwrap();
+        assert_eq!(f.type_tag(), VecFile::<u32>::default_type_tag());
+        assert_ne!(f.type_tag(), VecFile::<i32>::default_type_tag());
+        f.extend_from_slice(&[1, 0xffffffff]);
+        f.set_type_tag(0xabcd).unwrap();
+        std::mem::drop(f);
+
+        assert!(VecFile::<u32>::open(&path).is_err());
+        let f: VecFile<u32> = VecFile::open_with_type_tag(&path, 0xabcd).unwrap();
+        assert_eq!(f, vec![1, 0xffffffff]);
+        assert_eq!(f.clone().type_tag(), 0xabcd);
+        std::mem::drop(f);
+
+        // Reinterpreting is possible, but has to be asked for explicitly
+        let f: VecFile<i32> = unsafe { VecFile::open_unchecked(&path).unwrap() };
+        assert_eq!(f, vec![1, -1]);
+        assert_eq!(f.type_tag(), 0xabcd);
+    }
+
+    #[test]
+    fn storage_backends() {
+        let mut vec = vec![3u64, 1, 4, 1, 5, 9, 2, 6];
+        let mut vf: VecFile<u64, Vec<u8>, File> = vec.clone().try_into().unwrap();
+        vf.add_shadows(1).unwrap();
+        vf.add_shadow_in(File::temp(None).unwrap()).unwrap();
+        for i in 0..20 {
+            vf.push(&i);
+            vec.push(i);
+        }
+        vf.insert(2, &100);
+        vec.insert(2, 100);
+        assert_eq!(vf.remove(5), vec.remove(5));
+        assert_eq!(vf, vec);
+        assert!(vf.confirm_shadow_equivalence().unwrap());
+
+        let vf_clone = vf.clone();
+        assert_eq!(vf_clone, vec);
+        let vec_out: Vec<u64> = vf_clone.try_into().unwrap();
+        assert_eq!(vec_out, vec);
+
+        // Copying to a named file needs storage that can be backed by a file
+        let dir = tempfile::tempdir().unwrap();
+        assert!(vf.to_named_file(dir.path().join("mem.bin")).is_err());
+        let mut vf: VecFile<u64, MmapStorage, Vec<u8>> = vec.iter().cloned().collect();
+        vf.to_named_file(dir.path().join("mmap.bin")).unwrap();
+        vf.push(&7);
+        vec.push(7);
+        std::mem::drop(vf);
+        assert_eq!(VecFile::<u64>::open(dir.path().join("mmap.bin")).unwrap(), vec);
+    }
+
+    #[test]
+    fn open_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("open_invalid.bin");
+        let f: VecFile<u32> = VecFile::new_with_path(&path).unwrap();
+        std::mem::drop(f);
+
+        match VecFile::<u64>::open(&path).unwrap_err().downcast_ref::<Error>() {
+            Some(Error::ElementSizeMismatch(4, 8)) => (),
+            e => panic!("Unexpected result: {:?}", e),
+        }
+
+        match VecFile::<i32>::open(&path).unwrap_err().downcast_ref::<Error>() {
+            Some(Error::TypeTagMismatch(found, expected)) => {
+                assert_eq!(*found, VecFile::<u32>::default_type_tag());
+                assert_eq!(*expected, VecFile::<i32>::default_type_tag());
+            }
+            e => panic!("Unexpected result: {:?}", e),
+        }
+
+        std::fs::write(&path, b"not a vecfile").unwrap();
+        match VecFile::<u32>::open(&path).unwrap_err().downcast_ref::<Error>() {
+            Some(Error::InvalidMagic) => (),
+            e => panic!("Unexpected result: {:?}", e),
+        }
+    }
+
 
 
 
@@ -0,0 +1,684 @@
+//! Checks that a VecFile with shadows survives its storage failing, by injecting faults into it
+//! with FaultyStorage.
+
+use vecfile::*;
+
+type FaultyVecFile = VecFile<u32, FaultyStorage, FaultyStorage>;
+
+/// Builds a VecFile holding 0..len in faulty storage, configured by builder, and returns it with
+/// the handle to the storage's faults.
+fn faulty(len: u32, builder: &VecFileBuilder) -> (FaultyVecFile, Faults) {
+    let storage = FaultyStorage::new(Vec::new());
+    let faults = storage.faults();
+    let mut vf: FaultyVecFile = builder.build_in(storage).unwrap();
+    vf.extend_from_slice(&(0..len).collect::<Vec<_>>());
+    (vf, faults)
+}
+
+/// Returns a builder for a VecFile with checksums in small blocks, so that it has plenty of them.
+fn checksummed() -> VecFileBuilder {
+    let mut builder = VecFileBuilder::new();
+    builder.checksums(true).checksum_block_size(64);
+    builder
+}
+
+/// Pushes, inserts, sets and removes elements, checking that vf still matches a Vec afterwards
+/// and that its shadows are intact.
+///
+/// Checking the shadows reads the primary directly, without replacing it if it fails, so any
+/// faults that haven't happened yet are cleared first.
+fn workload(vf: &mut FaultyVecFile, faults: &Faults) {
+    let mut vec: Vec<u32> = vf.iter().collect();
+    for i in 0..20 {
+        vf.push(&i);
+        vec.push(i);
+    }
+    vf.insert(3, &100);
+    vec.insert(3, 100);
+    vf.set(10, &200);
+    vec[10] = 200;
+    assert_eq!(vf.remove(5), vec.remove(5));
+    assert_eq!(vf.pop(), vec.pop().unwrap());
+    assert_eq!(*vf, vec);
+    faults.clear();
+    assert!(vf.confirm_shadow_equivalence().unwrap());
+}
+
+#[test]
+fn faulty_storage() {
+    let mut storage = FaultyStorage::new(Vec::new());
+    let faults = storage.faults();
+    storage.write_all_at(&[1; 100], 0).unwrap();
+
+    // The failed write doesn't use up the corrupted one
+    faults.fail_nth_write(0);
+    faults.corrupt_nth_write(0);
+    assert!(storage.write_all_at(&[2], 0).is_err());
+    storage.write_all_at(&[2], 0).unwrap();
+    let mut buf = [0u8; 2];
+    storage.read_exact_at(&mut buf, 0).unwrap();
+    assert_eq!(buf, [!2, 1]);
+
+    // Storage that's already past the max size can still be overwritten and shrunk
+    faults.set_max_size(Some(50));
+    storage.write_all_at(&[3; 10], 90).unwrap();
+    assert!(storage.write_all_at(&[3; 10], 95).is_err());
+    storage.set_len(60).unwrap();
+    assert!(storage.set_len(70).is_err());
+    assert!(storage.write_all_at(&[4], 60).is_err());
+    storage.write_all_at(&[4], 59).unwrap();
+    assert_eq!(storage.size().unwrap(), 60);
+}
+
+#[test]
+fn primary_read_failure() {
+    let (mut vf, faults) = faulty(100, VecFileBuilder::new().shadows(2));
+    faults.fail_reads(true);
+
+    assert_eq!(vf.get(50), 50);
+    assert!(vf.iter().eq(0..100));
+    assert_eq!(vf.shadow_count(), 2);
+
+    // The faulty storage isn't used anymore
+    faults.fail_writes(true);
+    workload(&mut vf, &faults);
+    assert_eq!(vf.shadow_count(), 2);
+}
+
+#[test]
+fn primary_write_failure() {
+    let (mut vf, faults) = faulty(100, VecFileBuilder::new().shadows(2));
+    faults.fail_writes(true);
+
+    vf.push(&100);
+    assert!(vf.iter().eq(0..101));
+    assert_eq!(vf.shadow_count(), 2);
+    workload(&mut vf, &faults);
+}
+
+#[test]
+fn primary_resize_failure() {
+    let (mut vf, faults) = faulty(8, VecFileBuilder::new().shadows(1));
+    faults.fail_nth_set_len(0);
+
+    // Full, so this has to grow the storage
+    assert_eq!(vf.cap(), 8);
+    vf.push(&8);
+    assert!(vf.iter().eq(0..9));
+    assert_eq!(vf.shadow_count(), 1);
+    workload(&mut vf, &faults);
+}
+
+#[test]
+fn full_disk() {
+    let (mut vf, faults) = faulty(0, VecFileBuilder::new().shadows(1));
+    faults.set_max_size(Some(5000));
+
+    // Enough to need more than 5000 bytes
+    for i in 0..1000 {
+        vf.push(&i);
+    }
+    assert!(vf.iter().eq(0..1000));
+    assert_eq!(vf.shadow_count(), 1);
+    workload(&mut vf, &faults);
+}
+
+#[test]
+fn nth_operation_failures() {
+    // Fail every operation the workload does in turn, so failures happen part way through
+    // growing the storage, shifting elements and updating the header
+    for n in 0..60 {
+        let (mut vf, faults) = faulty(20, VecFileBuilder::new().shadows(1));
+        faults.fail_nth_read(n);
+        workload(&mut vf, &faults);
+        assert_eq!(vf.shadow_count(), 1);
+
+        let (mut vf, faults) = faulty(20, VecFileBuilder::new().shadows(1));
+        faults.fail_nth_write(n);
+        workload(&mut vf, &faults);
+        assert_eq!(vf.shadow_count(), 1);
+
+        let (mut vf, faults) = faulty(20, VecFileBuilder::new().shadows(1));
+        faults.fail_nth_set_len(n);
+        workload(&mut vf, &faults);
+        assert_eq!(vf.shadow_count(), 1);
+    }
+}
+
+#[test]
+fn repeated_failures() {
+    let (mut vf, mut faults) = faulty(50, VecFileBuilder::new().shadows(1));
+
+    // Each replacement primary fails in turn
+    for _ in 0..5 {
+        // The shadow that was added last is the one that replaces the primary
+        let shadow = FaultyStorage::new(Vec::new());
+        let next_faults = shadow.faults();
+        vf.add_shadow_in(shadow).unwrap();
+        let shadow_count = vf.shadow_count();
+
+        faults.fail_reads(true);
+        faults.fail_writes(true);
+        workload(&mut vf, &faults);
+        assert_eq!(vf.shadow_count(), shadow_count);
+        faults = next_faults;
+    }
+}
+
+#[test]
+fn shadow_resize_failure() {
+    let (mut vf, _) = faulty(0, &VecFileBuilder::new());
+    let shadow = FaultyStorage::new(Vec::new());
+    let faults = shadow.faults();
+    vf.add_shadow_in(shadow).unwrap();
+    faults.fail_nth_set_len(0);
+
+    // Growing the storage replaces the shadow rather than failing
+    for i in 0..100 {
+        vf.push(&i);
+    }
+    assert_eq!(vf.shadow_count(), 1);
+    assert_eq!(vf.failed_shadow_count(), 1);
+    assert!(vf.iter().eq(0..100));
+    assert!(vf.confirm_shadow_equivalence().unwrap());
+}
+
+#[test]
+fn shadow_write_failure() {
+    let (mut vf, _) = faulty(10, VecFileBuilder::new().shadows(1));
+    let shadow = FaultyStorage::new(Vec::new());
+    let faults = shadow.faults();
+    vf.add_shadow_in(shadow).unwrap();
+    faults.fail_writes(true);
+
+    // The write succeeds since the primary did, and the shadow is replaced
+    vf.set(5, &50);
+    assert_eq!(vf.get(5), 50);
+    assert_eq!(vf.shadow_count(), 2);
+    assert_eq!(vf.failed_shadow_count(), 1);
+    assert!(vf.confirm_shadow_equivalence().unwrap());
+
+    workload(&mut vf, &faults);
+    assert_eq!(vf.shadow_count(), 2);
+    assert_eq!(vf.failed_shadow_count(), 1);
+}
+
+#[test]
+fn sync_failure() {
+    let (mut vf, faults) = faulty(10, VecFileBuilder::new().shadows(1));
+    let shadow_faults = add_faulty_shadow(&vf);
+    shadow_faults.fail_syncs(true);
+
+    // The sync succeeds since the primary did, and the shadow is replaced
+    vf.sync().unwrap();
+    assert_eq!(vf.shadow_count(), 2);
+    assert_eq!(vf.failed_shadow_count(), 1);
+    assert!(vf.confirm_shadow_equivalence().unwrap());
+
+    // The primary failing is the same as for any other operation
+    faults.fail_nth_sync(0);
+    vf.sync().unwrap();
+    assert_eq!(vf.health().failovers, 1);
+    assert_eq!(vf.shadow_count(), 2);
+    assert!(vf.iter().eq(0..10));
+    workload(&mut vf, &faults);
+}
+
+#[test]
+fn nth_shadow_write_failures() {
+    for n in 0..40 {
+        let (mut vf, _) = faulty(20, VecFileBuilder::new().shadows(1));
+        let shadow = FaultyStorage::new(Vec::new());
+        let faults = shadow.faults();
+        vf.add_shadow_in(shadow).unwrap();
+        faults.fail_nth_write(n);
+
+        workload(&mut vf, &faults);
+        assert_eq!(vf.shadow_count(), 2);
+        assert!(vf.failed_shadow_count() <= 1);
+    }
+}
+
+#[test]
+fn unreplaceable_shadow() {
+    let shadow_dir = tempfile::tempdir().unwrap();
+    let mut vf: VecFile<u32, FaultyStorage, FaultyStorage<std::fs::File>> =
+        VecFileBuilder::new()
+            .shadow_dir(shadow_dir.path())
+            .shadows(1)
+            .build_in(FaultyStorage::new(Vec::new()))
+            .unwrap();
+    vf.extend_from_slice(&[1, 2, 3]);
+    let shadow = FaultyStorage::new(tempfile::tempfile().unwrap());
+    let faults = shadow.faults();
+    vf.add_shadow_in(shadow).unwrap();
+
+    // Without the directory, no replacement shadows can be made
+    shadow_dir.close().unwrap();
+    faults.fail_writes(true);
+
+    // The failed shadow is still removed rather than left out of date
+    vf.set(0, &10);
+    assert_eq!(vf, vec![10, 2, 3]);
+    assert_eq!(vf.shadow_count(), 1);
+    assert_eq!(vf.failed_shadow_count(), 1);
+    assert!(vf.confirm_shadow_equivalence().unwrap());
+}
+
+#[test]
+fn failing_new_shadow() {
+    let (mut vf, _) = faulty(10, &VecFileBuilder::new());
+
+    // Storage that can't be written to isn't added as a shadow
+    let shadow = FaultyStorage::new(Vec::new());
+    shadow.faults().fail_writes(true);
+    assert!(vf.add_shadow_in(shadow).is_err());
+    assert_eq!(vf.shadow_count(), 0);
+
+    vf.add_shadows(1).unwrap();
+    vf.push(&10);
+    assert!(vf.iter().eq(0..11));
+    assert!(vf.confirm_shadow_equivalence().unwrap());
+}
+
+#[test]
+fn primary_failure_while_adding_shadow() {
+    let (vf, faults) = faulty(100, VecFileBuilder::new().shadows(1));
+    faults.fail_reads(true);
+
+    // Copying the primary into the new shadow fails, so the primary is replaced part way through
+    vf.add_shadows(1).unwrap();
+    assert_eq!(vf.shadow_count(), 2);
+    assert!(vf.iter().eq(0..100));
+}
+
+#[test]
+fn no_shadows() {
+    let (mut vf, faults) = faulty(10, &VecFileBuilder::new());
+
+    // Failures are returned as errors rather than panicking
+    faults.fail_reads(true);
+    assert!(vf.try_get(0).is_err());
+    assert!(vf.try_iter().any(|e| e.is_err()));
+    faults.fail_reads(false);
+
+    faults.fail_writes(true);
+    assert!(vf.try_set(0, &5).is_err());
+    assert!(vf.try_extend_from_slice(&[1; 100]).is_err());
+    faults.clear();
+
+    assert_eq!(vf.get(0), 0);
+    assert!(vf.iter().eq(0..10));
+}
+
+#[test]
+fn corruption() {
+    let (mut vf, faults) = faulty(10, VecFileBuilder::new().shadows(1));
+
+    // A write that succeeds but stores the wrong bytes goes unnoticed by the primary, but its
+    // shadow no longer matches
+    faults.corrupt_nth_write(0);
+    vf.set(5, &50);
+    assert_ne!(vf.get(5), 50);
+    assert!(!vf.confirm_shadow_equivalence().unwrap());
+}
+
+#[test]
+fn named_shadow_replaces_primary() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("named.bin");
+    let storage = FaultyStorage::new(tempfile::tempfile().unwrap());
+    let faults = storage.faults();
+    let mut vf: VecFile<u32, FaultyStorage<std::fs::File>> =
+        VecFileBuilder::new().build_in(storage).unwrap();
+    vf.extend_from_slice(&[1, 2, 3]);
+    vf.add_shadow_at(&path).unwrap();
+    assert_eq!(vf.shadow_paths().len(), 1);
+
+    // Once the named shadow is the primary it's no longer listed as a shadow, in memory or in
+    // its own header
+    faults.fail_reads(true);
+    assert_eq!(vf.get(1), 2);
+    assert!(vf.shadow_paths().is_empty());
+    vf.push(&4);
+    std::mem::drop(vf);
+
+    let vf: VecFile<u32> = VecFile::open(&path).unwrap();
+    assert_eq!(vf, vec![1, 2, 3, 4]);
+    assert_eq!(vf.shadow_count(), 0);
+}
+
+#[test]
+fn checksummed_corruption() {
+    let (mut vf, faults) = faulty(100, checksummed().shadows(1));
+
+    // Unlike corruption, the write is caught the next time it's read, and the primary is
+    // replaced like it would be for a failed read
+    faults.corrupt_nth_write(0);
+    vf.set(5, &50);
+    assert_eq!(vf.get(5), 50);
+    assert_eq!(vf.shadow_count(), 1);
+    assert!(vf.confirm_shadow_equivalence().unwrap());
+    workload(&mut vf, &faults);
+}
+
+#[test]
+fn checksum_mismatch() {
+    let (mut vf, faults) = faulty(100, &checksummed());
+    faults.corrupt_nth_write(0);
+    vf.set(5, &50);
+
+    // Without a shadow the block that was corrupted can't be read, but the rest can
+    let e = vf.try_get(5).unwrap_err();
+    assert!(matches!(e.downcast_ref::<Error>(), Some(Error::ChecksumMismatch { block: 0 })));
+    assert!(vf.try_iter().any(|e| e.is_err()));
+    assert!(vf.try_set(6, &60).is_err());
+    assert_eq!(vf.get(16), 16);
+    assert!(vf.iter_range(16..).eq(16..100));
+
+    // A shadow added now can't copy it either
+    assert!(vf.add_shadows(1).is_err());
+}
+
+#[test]
+fn nth_corrupt_writes() {
+    // Corrupt every write the workload does in turn, including those of the checksums
+    // themselves and those made while growing the storage
+    for n in 0..60 {
+        let (mut vf, faults) = faulty(20, checksummed().shadows(1));
+        faults.corrupt_nth_write(n);
+        workload(&mut vf, &faults);
+        assert_eq!(vf.shadow_count(), 1);
+    }
+}
+
+/// Adds a shadow in faulty storage to vf, returning the handle to its faults.
+fn add_faulty_shadow(vf: &FaultyVecFile) -> Faults {
+    let shadow = FaultyStorage::new(Vec::new());
+    let faults = shadow.faults();
+    vf.add_shadow_in(shadow).unwrap();
+    faults
+}
+
+#[test]
+fn scrub_clean() {
+    let (vf, _) = faulty(2000, VecFileBuilder::new().shadows(2));
+    let report = vf.scrub();
+    assert!(report.is_clean());
+    assert_eq!(report.block_size, 4096);
+    assert_eq!(report.blocks, 2);
+
+    let (vf, _) = faulty(0, &VecFileBuilder::new());
+    assert_eq!(vf.scrub(), ScrubReport { block_size: 4096, blocks: 0, mismatches: Vec::new() });
+}
+
+#[test]
+fn repair_by_majority() {
+    let (mut vf, _) = faulty(2000, &VecFileBuilder::new());
+    add_faulty_shadow(&vf);
+    let faults = add_faulty_shadow(&vf);
+
+    // The corrupted shadow is outvoted by the other two copies
+    faults.corrupt_nth_write(0);
+    vf.set(1500, &0);
+    let mismatch = BlockMismatch { block: 1, bad_copies: vec![CopyId::Shadow(1)], resolved: true };
+    assert_eq!(vf.scrub().mismatches, vec![mismatch.clone()]);
+    assert!(!vf.confirm_shadow_equivalence().unwrap());
+
+    assert_eq!(vf.repair().unwrap().mismatches, vec![mismatch]);
+    assert!(vf.scrub().is_clean());
+    assert!(vf.confirm_shadow_equivalence().unwrap());
+    assert_eq!(vf.shadow_count(), 2);
+
+    // An outvoted primary has failed, so it's replaced by a shadow rather than rewritten
+    let (mut vf, faults) = faulty(2000, &VecFileBuilder::new());
+    add_faulty_shadow(&vf);
+    add_faulty_shadow(&vf);
+    faults.corrupt_nth_write(0);
+    vf.set(1500, &0);
+    let mismatch = BlockMismatch { block: 1, bad_copies: vec![CopyId::Primary], resolved: true };
+    assert_eq!(vf.repair().unwrap().mismatches, vec![mismatch]);
+    assert!(vf.scrub().is_clean());
+    assert_eq!(vf.get(1500), 0);
+    assert_eq!(vf.health().failovers, 1);
+    assert_eq!(vf.shadow_count(), 2);
+}
+
+#[test]
+fn unresolved_mismatch() {
+    let (mut vf, _) = faulty(100, &VecFileBuilder::new());
+    let faults = add_faulty_shadow(&vf);
+
+    // Two copies that disagree without checksums can't be told apart, so they're left alone
+    faults.corrupt_nth_write(0);
+    vf.set(5, &50);
+    let mismatch = BlockMismatch { block: 0, bad_copies: vec![CopyId::Shadow(0)], resolved: false };
+    assert_eq!(vf.repair().unwrap().mismatches, vec![mismatch.clone()]);
+    assert_eq!(vf.scrub().mismatches, vec![mismatch]);
+    assert_eq!(vf.get(5), 50);
+}
+
+#[test]
+fn repair_by_checksum() {
+    let (mut vf, faults) = faulty(100, checksummed().shadows(1));
+
+    // Only the shadow matches its checksum, so it's right even though it's one copy of two
+    faults.corrupt_nth_write(0);
+    vf.set(40, &400);
+    let report = vf.repair().unwrap();
+    assert_eq!(report.block_size, 64);
+    assert_eq!(report.blocks, 7);
+    let mismatch = BlockMismatch { block: 2, bad_copies: vec![CopyId::Primary], resolved: true };
+    assert_eq!(report.mismatches, vec![mismatch]);
+
+    // The primary was fixed in place rather than replaced
+    assert!(vf.scrub().is_clean());
+    faults.fail_reads(true);
+    assert!(vf.scrub().mismatches.iter().all(|m| m.bad_copies == vec![CopyId::Primary]));
+    faults.fail_reads(false);
+    assert_eq!(vf.get(40), 400);
+    assert_eq!(vf.failed_shadow_count(), 0);
+    workload(&mut vf, &faults);
+}
+
+#[test]
+fn unrepairable_block() {
+    let (mut vf, faults) = faulty(100, &checksummed());
+    faults.corrupt_nth_write(0);
+    vf.set(40, &400);
+
+    // No copy of the block matches its checksum
+    let mismatch = BlockMismatch { block: 2, bad_copies: vec![CopyId::Primary], resolved: false };
+    assert_eq!(vf.repair().unwrap().mismatches, vec![mismatch]);
+    assert!(vf.try_get(40).is_err());
+}
+
+/// Returns a builder for a VecFile whose reads are voted on by every copy.
+fn majority() -> VecFileBuilder {
+    let mut builder = VecFileBuilder::new();
+    builder.read_mode(ReadMode::Majority);
+    builder
+}
+
+#[test]
+fn majority_reads() {
+    for copy in 0..3 {
+        let (mut vf, primary) = faulty(100, &majority());
+        let faults = [primary, add_faulty_shadow(&vf), add_faulty_shadow(&vf)];
+        assert_eq!(vf.read_mode(), ReadMode::Majority);
+
+        // Whichever copy is corrupted is outvoted, and fixed by the read. A shadow is
+        // overwritten, while the primary has failed and is replaced.
+        faults[copy].corrupt_nth_write(0);
+        vf.set(5, &50);
+        assert!(!vf.scrub().is_clean());
+        assert_eq!(vf.get(5), 50);
+        assert!(vf.scrub().is_clean());
+        assert_eq!(vf.shadow_count(), 2);
+        assert_eq!(vf.failed_shadow_count(), 0);
+        assert_eq!(vf.health().failovers, if copy == 0 { 1 } else { 0 });
+    }
+
+    // Only reads are voted on, the primary is trusted otherwise
+    let (mut vf, primary) = faulty(100, &majority());
+    let faults = [primary, add_faulty_shadow(&vf), add_faulty_shadow(&vf)];
+    vf.set_read_mode(ReadMode::Primary);
+    faults[0].corrupt_nth_write(0);
+    vf.set(5, &50);
+    assert_ne!(vf.get(5), 50);
+}
+
+#[test]
+fn majority_read_failures() {
+    let (mut vf, primary) = faulty(100, &majority());
+    let faults = [primary, add_faulty_shadow(&vf), add_faulty_shadow(&vf)];
+
+    // A primary that can't be read doesn't get a vote, and is replaced by a shadow
+    faults[0].fail_reads(true);
+    assert!(vf.iter().eq(0..100));
+    assert_eq!(vf.health().failovers, 1);
+    assert_eq!(vf.shadow_count(), 2);
+    assert_eq!(vf.failed_shadow_count(), 0);
+    assert!(vf.confirm_shadow_equivalence().unwrap());
+
+    // Shadows that can't be read don't get a vote either, and are left alone since they
+    // haven't failed a write
+    faults[1].fail_reads(true);
+    assert!(vf.iter().eq(0..100));
+    assert_eq!(vf.health().failovers, 1);
+    assert_eq!(vf.failed_shadow_count(), 0);
+    faults[1].fail_reads(false);
+
+    for n in 0..60 {
+        let (mut vf, primary) = faulty(20, &majority());
+        let faults = [primary, add_faulty_shadow(&vf), add_faulty_shadow(&vf)];
+        faults[0].corrupt_nth_write(n);
+        workload(&mut vf, &faults[0]);
+    }
+}
+
+#[test]
+fn no_majority() {
+    let (mut vf, primary) = faulty(100, &majority());
+    let faults = [primary, add_faulty_shadow(&vf)];
+    faults[1].corrupt_nth_write(0);
+    vf.set(5, &50);
+
+    // Two copies that disagree can't outvote each other
+    let e = vf.try_get(5).unwrap_err();
+    assert!(matches!(e.downcast_ref::<Error>(), Some(Error::NoMajority)));
+    assert_eq!(vf.get(6), 6);
+
+    // But with checksums, the copy that fails its checksum doesn't get a vote
+    let (mut vf, faults) = faulty(100, majority().checksums(true).shadows(1));
+    faults.corrupt_nth_write(0);
+    vf.set(5, &50);
+    assert_eq!(vf.get(5), 50);
+    assert!(vf.scrub().is_clean());
+}
+
+/// Sets an observer on vf that collects its events.
+fn observe(vf: &mut FaultyVecFile) -> std::sync::Arc<std::sync::Mutex<Vec<Event>>> {
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let observed = events.clone();
+    vf.set_observer(move |event| observed.lock().unwrap().push(event.clone()));
+    events
+}
+
+#[test]
+fn health() {
+    let (mut vf, faults) = faulty(10, VecFileBuilder::new().shadows(1));
+    let events = observe(&mut vf);
+    assert_eq!(vf.health(), Health { shadows: 1, ..Default::default() });
+
+    faults.fail_reads(true);
+    assert_eq!(vf.get(3), 3);
+    let shadow_faults = add_faulty_shadow(&vf);
+    shadow_faults.fail_nth_write(0);
+    vf.set(0, &10);
+
+    let health = vf.health();
+    assert_eq!(health.failovers, 1);
+    assert_eq!(health.failed_shadows, 1);
+    assert_eq!(health.shadows, 2);
+    assert!(health.last_error.is_some());
+    let events = events.lock().unwrap();
+    assert!(matches!(events[..], [Event::Failover { .. },
+                                  Event::ShadowFailed { replaced: true, .. }]));
+    assert_eq!(health.last_error.as_deref(), events[1].error());
+}
+
+#[test]
+fn repair_events() {
+    let (mut vf, primary) = faulty(100, &majority());
+    let faults = [primary, add_faulty_shadow(&vf), add_faulty_shadow(&vf)];
+    let events = observe(&mut vf);
+
+    // Both an outvoted read and a repair report the copy they fixed
+    faults[2].corrupt_nth_write(0);
+    vf.set(5, &50);
+    assert_eq!(vf.get(5), 50);
+    vf.set_read_mode(ReadMode::Primary);
+    faults[1].corrupt_nth_write(0);
+    vf.set(90, &900);
+    assert_eq!(vf.repair().unwrap().mismatches.len(), 1);
+    assert!(vf.scrub().is_clean());
+
+    let health = vf.health();
+    assert_eq!(health.repaired_copies, 2);
+    assert_eq!(health.failovers, 0);
+    assert_eq!(health.last_error, None);
+    let events = events.lock().unwrap();
+    assert!(matches!(events[..], [Event::CopyRepaired { copy: CopyId::Shadow(1), .. },
+                                  Event::CopyRepaired { copy: CopyId::Shadow(0), .. }]));
+}
+
+#[test]
+fn shadow_retry_events() {
+    // The primary failing part way through copying it into a new shadow retries the copy
+    let (mut vf, faults) = faulty(100, VecFileBuilder::new().shadows(1));
+    let events = observe(&mut vf);
+    faults.fail_reads(true);
+    vf.add_shadows(1).unwrap();
+    let health = vf.health();
+    assert_eq!(health.failovers, 1);
+    assert!(health.shadow_retries > 0);
+    assert!(events.lock().unwrap().iter().any(|event| matches!(event, Event::ShadowRetry { .. })));
+
+    // Without its directory, making a replacement shadow is retried and then given up on
+    let shadow_dir = tempfile::tempdir().unwrap();
+    let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let observed = events.clone();
+    let mut vf: VecFile<u32, FaultyStorage, FaultyStorage<std::fs::File>> =
+        VecFileBuilder::new()
+            .shadow_dir(shadow_dir.path())
+            .observer(move |event| observed.lock().unwrap().push(event.clone()))
+            .build_in(FaultyStorage::new(Vec::new()))
+            .unwrap();
+    let shadow = FaultyStorage::new(tempfile::tempfile().unwrap());
+    let faults = shadow.faults();
+    vf.add_shadow_in(shadow).unwrap();
+    shadow_dir.close().unwrap();
+    faults.fail_writes(true);
+    vf.push(&1);
+
+    let health = vf.health();
+    assert_eq!(health.shadow_retries, 4);
+    assert_eq!(health.failed_shadows, 1);
+    assert_eq!(health.shadows, 0);
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 5);
+    assert!(matches!(events.last(), Some(Event::ShadowFailed { replaced: false, .. })));
+}
+
+#[test]
+fn unrecovered_events() {
+    let (mut vf, faults) = faulty(10, &VecFileBuilder::new());
+    let events = observe(&mut vf);
+    faults.fail_reads(true);
+    assert!(vf.try_get(0).is_err());
+
+    assert_eq!(vf.health().failovers, 0);
+    assert!(vf.health().last_error.is_some());
+    assert!(matches!(events.lock().unwrap()[..], [Event::Unrecovered { .. }]));
+}
@@ -0,0 +1,218 @@
+//! Checks that VecFile behaves like Vec, by applying the same random sequence of operations to
+//! both and comparing them after each one.
+
+use std::fmt::Debug;
+use desse::{Desse, DesseSized};
+use proptest::prelude::*;
+use vecfile::*;
+
+/// The most elements either collection is resized to, which keeps the cases quick.
+const MAX_LEN: u64 = 64;
+
+/// The most shadows a VecFile is given at once.
+const MAX_SHADOWS: usize = 3;
+
+// Rust pads this out in memory, but it's serialized without the padding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Desse, DesseSized)]
+struct Record {
+    id: u32,
+    flag: u8,
+    value: i64,
+    tag: [u8; 3],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Desse, DesseSized)]
+enum Shape {
+    Point,
+    Circle(u32),
+    Rect { w: u16, h: u8 },
+}
+
+fn record() -> impl Strategy<Value = Record> + Clone {
+    any::<(u32, u8, i64, [u8; 3])>().prop_map(|(id, flag, value, tag)| {
+        Record { id, flag, value, tag }
+    })
+}
+
+fn shape() -> impl Strategy<Value = Shape> + Clone {
+    prop_oneof![
+        Just(Shape::Point),
+        any::<u32>().prop_map(Shape::Circle),
+        any::<(u16, u8)>().prop_map(|(w, h)| Shape::Rect { w, h }),
+    ]
+}
+
+/// An operation to apply to both collections. Indices go past the end of the collections so
+/// that the out of range cases are covered too.
+#[derive(Debug, Clone)]
+enum Op<T> {
+    Push(T),
+    Pop,
+    Insert(u64, T),
+    Remove(u64),
+    Set(u64, T),
+    Truncate(u64),
+    Resize(u64, T),
+    Extend(Vec<T>),
+    SplitOff(u64),
+    Clone,
+    AddShadows(usize),
+    RemoveShadows(usize),
+}
+
+fn ops<T, E>(element: E) -> impl Strategy<Value = Vec<Op<T>>>
+where T: Debug + Clone + 'static,
+      E: Strategy<Value = T> + Clone + 'static {
+    let index = 0..MAX_LEN + 2;
+    let op = prop_oneof![
+        element.clone().prop_map(Op::Push),
+        Just(Op::Pop),
+        (index.clone(), element.clone()).prop_map(|(i, e)| Op::Insert(i, e)),
+        index.clone().prop_map(Op::Remove),
+        (index.clone(), element.clone()).prop_map(|(i, e)| Op::Set(i, e)),
+        index.clone().prop_map(Op::Truncate),
+        (0..MAX_LEN, element.clone()).prop_map(|(len, e)| Op::Resize(len, e)),
+        prop::collection::vec(element, 0..16).prop_map(Op::Extend),
+        index.prop_map(Op::SplitOff),
+        Just(Op::Clone),
+        (1..=MAX_SHADOWS).prop_map(Op::AddShadows),
+        (1..=MAX_SHADOWS).prop_map(Op::RemoveShadows),
+    ];
+    prop::collection::vec(op, 1..40)
+}
+
+/// Applies op to both collections. Where Vec would panic, VecFile has to return an error and
+/// leave its elements as they were.
+fn apply<T>(vec: &mut Vec<T>, vf: &mut VecFile<T>, op: &Op<T>) -> Result<(), TestCaseError>
+where T: Desse + DesseSized + Debug + Clone + PartialEq + Eq {
+    let len = vec.len() as u64;
+    match op {
+        Op::Push(e) => {
+            vec.push(e.clone());
+            vf.push(e);
+        }
+        Op::Pop => match vec.pop() {
+            Some(e) => prop_assert_eq!(vf.pop(), e),
+            None => prop_assert!(vf.try_pop().is_err()),
+        },
+        &Op::Insert(index, ref e) if index <= len => {
+            vec.insert(index as usize, e.clone());
+            vf.insert(index, e);
+        }
+        Op::Insert(index, e) => prop_assert!(vf.try_insert(*index, e).is_err()),
+        &Op::Remove(index) if index < len => {
+            prop_assert_eq!(vf.remove(index), vec.remove(index as usize));
+        }
+        Op::Remove(index) => prop_assert!(vf.try_remove(*index).is_err()),
+        &Op::Set(index, ref e) if index < len => {
+            vec[index as usize] = e.clone();
+            vf.set(index, e);
+        }
+        Op::Set(index, e) => prop_assert!(vf.try_set(*index, e).is_err()),
+        Op::Truncate(new_len) => {
+            vec.truncate(*new_len as usize);
+            vf.truncate(*new_len);
+        }
+        Op::Resize(new_len, e) => {
+            vec.resize(*new_len as usize, e.clone());
+            vf.resize(*new_len, e).unwrap();
+        }
+        Op::Extend(slice) => {
+            vec.extend_from_slice(slice);
+            vf.extend_from_slice(slice);
+        }
+        &Op::SplitOff(at) if at <= len => {
+            let vec_other = vec.split_off(at as usize);
+            let vf_other = vf.split_off(at);
+            prop_assert_eq!(vf_other.shadow_count(), vf.shadow_count());
+            prop_assert_eq!(vf_other, vec_other);
+        }
+        Op::SplitOff(at) => prop_assert!(vf.try_split_off(*at).is_err()),
+        Op::Clone => {
+            let clone = vf.clone();
+            prop_assert_eq!(clone.shadow_count(), vf.shadow_count());
+            prop_assert_eq!(&clone, &*vf);
+            // Carry on with the clone, so it's checked as much as the original
+            *vf = clone;
+        }
+        Op::AddShadows(count) => {
+            let count = std::cmp::min(*count, MAX_SHADOWS - vf.shadow_count());
+            vf.add_shadows(count).unwrap();
+        }
+        Op::RemoveShadows(count) => {
+            let expected = vf.shadow_count().saturating_sub(*count);
+            vf.remove_shadows(*count);
+            prop_assert_eq!(vf.shadow_count(), expected);
+        }
+    }
+    Ok(())
+}
+
+/// Applies every op to both an empty Vec and an empty VecFile, checking they're still the same
+/// after each one.
+fn check<T>(ops: &[Op<T>]) -> Result<(), TestCaseError>
+where T: Desse + DesseSized + Debug + Clone + PartialEq + Eq {
+    check_in(VecFile::new(), ops)
+}
+
+/// Like check, but with the elements checksummed in blocks that are small enough for the ops to
+/// span several of them.
+fn check_checksummed<T>(ops: &[Op<T>]) -> Result<(), TestCaseError>
+where T: Desse + DesseSized + Debug + Clone + PartialEq + Eq {
+    let vf = VecFileBuilder::new().checksums(true).checksum_block_size(16).build().unwrap();
+    check_in(vf, ops)
+}
+
+fn check_in<T>(mut vf: VecFile<T>, ops: &[Op<T>]) -> Result<(), TestCaseError>
+where T: Desse + DesseSized + Debug + Clone + PartialEq + Eq {
+    let mut vec = Vec::new();
+    for op in ops {
+        apply(&mut vec, &mut vf, op)?;
+        prop_assert_eq!(vf.len(), vec.len() as u64);
+        prop_assert_eq!(&vf, &vec);
+        prop_assert!(vf.confirm_shadow_equivalence().unwrap());
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn matches_vec_u8(ops in ops(any::<u8>())) {
+        check(&ops)?;
+    }
+
+    #[test]
+    fn matches_vec_u32(ops in ops(any::<u32>())) {
+        check(&ops)?;
+    }
+
+    #[test]
+    fn matches_vec_i64(ops in ops(any::<i64>())) {
+        check(&ops)?;
+    }
+
+    #[test]
+    fn matches_vec_array(ops in ops(any::<[u16; 5]>())) {
+        check(&ops)?;
+    }
+
+    #[test]
+    fn matches_vec_struct(ops in ops(record())) {
+        check(&ops)?;
+    }
+
+    #[test]
+    fn matches_vec_enum(ops in ops(shape())) {
+        check(&ops)?;
+    }
+
+    #[test]
+    fn matches_vec_checksummed_u32(ops in ops(any::<u32>())) {
+        check_checksummed(&ops)?;
+    }
+
+    #[test]
+    fn matches_vec_checksummed_array(ops in ops(any::<[u16; 5]>())) {
+        check_checksummed(&ops)?;
+    }
+}